@@ -0,0 +1,242 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Canon's CR3 is an ISO base media file. Its `moov` box holds a `uuid` box of Canon's own, which
+// holds each Exif IFD as a separate TIFF structure: CMT1 for IFD0, CMT2 for the Exif IFD and CMT4
+// for the GPS IFD. Each one's tags sit in its IFD0, so they are moved back to the IFDs they belong
+// to and written out as one TIFF structure again.
+
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+
+use exif::experimental::Writer;
+use exif::Field;
+use exif::In;
+use exif::Reader;
+
+use crate::isobmff;
+use crate::isobmff::Boxes;
+use crate::quicktime;
+
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+// the boxes holding the IFDs, and the context of the tags in each
+const IFD_BOXES: [(&[u8; 4], exif::Context); 3] = [
+    (b"CMT1", exif::Context::Tiff),
+    (b"CMT2", exif::Context::Exif),
+    (b"CMT4", exif::Context::Gps),
+];
+
+// the tags that point to other IFDs, which the writer makes anew
+const POINTERS: [exif::Tag; 3] = [
+    exif::Tag::ExifIFDPointer,
+    exif::Tag::GPSInfoIFDPointer,
+    exif::Tag::InteropIFDPointer,
+];
+
+/// Returns whether `head`, the start of a file, is the start of a CR3 file.
+pub(crate) fn is_cr3(head: &[u8]) -> bool {
+    match isobmff::brands(head) {
+        Some(brands) => brands[0] == *b"crx ",
+        None => false,
+    }
+}
+
+/// Reads the Exif attributes of the CR3 file `reader` is at the start of, as one TIFF structure.
+pub(crate) fn get_exif_attr<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let moov = isobmff::read_box(reader, b"moov", quicktime::MAX_MOVIE_LEN)?
+        .ok_or(exif::Error::NotFound("CR3"))?;
+    let canon = Boxes::new(&moov)
+        .filter(|(kind, _)| kind == b"uuid")
+        .find_map(|(_, body)| body.strip_prefix(&CANON_UUID[..]))
+        .ok_or(exif::Error::NotFound("CR3"))?;
+
+    let mut fields = Vec::new();
+    let mut error = None;
+    for (kind, context) in IFD_BOXES.iter() {
+        let ifd = match isobmff::find_box(canon, kind) {
+            Some(ifd) => ifd,
+            None => continue,
+        };
+        // a box that cannot be read does not spoil the others
+        let exif = match Reader::new().read_raw(ifd.to_vec()) {
+            Ok(exif) => exif,
+            Err(err) => {
+                error.get_or_insert(err);
+                continue;
+            }
+        };
+        let moved = exif
+            .fields()
+            .filter(|field| field.ifd_num == In::PRIMARY)
+            .filter(|field| !POINTERS.contains(&field.tag))
+            // the writer cannot write values kamadak-exif could not read
+            .filter(|field| !matches!(field.value, exif::Value::Unknown(..)))
+            .map(|field| {
+                // only the tags of IFD0 itself are out of place, not those it points to
+                let tag = match field.tag {
+                    exif::Tag(exif::Context::Tiff, number) => exif::Tag(*context, number),
+                    tag => tag,
+                };
+                Field {
+                    tag,
+                    ifd_num: In::PRIMARY,
+                    value: field.value.clone(),
+                }
+            });
+        for field in moved {
+            // the writer takes each tag once, so the first box to have it wins
+            if !fields.iter().any(|found: &Field| found.tag == field.tag) {
+                fields.push(field);
+            }
+        }
+    }
+    if fields.is_empty() {
+        return Err(error.unwrap_or(exif::Error::NotFound("CR3")));
+    }
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer.write(&mut tiff, true)?;
+    Ok(tiff.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::isobmff::build::*;
+    use crate::tests::ascii;
+    use crate::tests::tiff;
+    use crate::DateSource;
+
+    use exif::Tag;
+
+    // Builds a CR3 file whose IFDs hold the given fields, each in its IFD0
+    fn cr3(ifd0: &[Field], exif: &[Field]) -> Vec<u8> {
+        cr3_from(&tiff(ifd0), &tiff(exif))
+    }
+
+    // Builds a CR3 file from the TIFF structures of its CMT1 and CMT2 boxes
+    fn cr3_from(cmt1: &[u8], cmt2: &[u8]) -> Vec<u8> {
+        cr3_boxes(&[(b"CMT1", cmt1), (b"CMT2", cmt2), (b"CMT3", b"maker notes")])
+    }
+
+    // Builds a CR3 file whose Canon box holds the given boxes
+    fn cr3_boxes(boxes: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut canon = CANON_UUID.to_vec();
+        canon.extend(boxed(b"CNCV", b"CanonCR3_001/00.09.00/00.00.00"));
+        for (kind, body) in boxes {
+            canon.extend(boxed(kind, body));
+        }
+        // a movie header with a creation time, which is not read as a movie's
+        let mut mvhd = vec![0; 100];
+        mvhd[..4].copy_from_slice(&3_295_007_761u32.to_be_bytes());
+        let mut moov = full_boxed(b"mvhd", 0, &mvhd);
+        moov.extend(boxed(b"uuid", &canon));
+
+        let mut file = ftyp(b"crx ", &[b"crx ", b"isom"]);
+        file.extend(boxed(b"moov", &moov));
+        file.extend(boxed(b"mdat", &[0; 32]));
+        file
+    }
+
+    // A field of IFD0 as CR3 stores it, taken out of the IFD it belongs to
+    fn in_ifd0(tag: Tag, value: &str) -> Field {
+        let mut field = ascii(tag, In::PRIMARY, value);
+        field.tag = Tag(exif::Context::Tiff, tag.number());
+        field
+    }
+
+    #[test]
+    fn canon_ifds() {
+        let file = cr3(
+            &[ascii(Tag::DateTime, In::PRIMARY, "2021:02:03 04:05:06")],
+            &[
+                in_ifd0(Tag::DateTimeOriginal, "2020:11:27 13:34:09"),
+                in_ifd0(Tag::OffsetTimeOriginal, "+01:00"),
+            ],
+        );
+        assert!(is_cr3(&file));
+
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1606480449);
+        assert_eq!(date.offset, chrono::FixedOffset::east_opt(3600));
+
+        let policy = crate::DatePolicy::new().disable(DateSource::ExifDateTimeOriginal);
+        let mut reader = Cursor::new(&file);
+        let date = crate::try_get_image_date_from_reader_with(&mut reader, &policy).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+        assert_eq!(date.timestamp, 1612325106);
+
+        let path = crate::tests::write_temp("canon.cr3", &file);
+        let candidates = crate::all_image_dates(&path).unwrap();
+        assert!(candidates
+            .iter()
+            .all(|candidate| !crate::QUICKTIME_SOURCES.contains(&candidate.source)));
+    }
+
+    #[test]
+    fn pointers_in_ifd0() {
+        // a CMT1 that points to an Exif IFD of its own, as a full TIFF structure would
+        let cmt1 = tiff(&[
+            ascii(Tag::Make, In::PRIMARY, "Canon"),
+            ascii(Tag::DateTimeOriginal, In::PRIMARY, "2020:11:27 13:34:09"),
+        ]);
+        let file = cr3_from(&cmt1, &tiff(&[ascii(Tag::Make, In::PRIMARY, "unused")]));
+        let exif = Reader::new()
+            .read_raw(get_exif_attr(&mut Cursor::new(&file)).unwrap())
+            .unwrap();
+        let original = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).unwrap();
+        assert_eq!(original.tag, Tag::DateTimeOriginal);
+        let make = exif.get_field(Tag::Make, In::PRIMARY).unwrap();
+        assert_eq!(make.display_value().to_string(), "\"Canon\"");
+        assert!(exif
+            .fields()
+            .all(|field| field.tag.number() != 0x8769 || field.tag == Tag::ExifIFDPointer));
+    }
+
+    #[test]
+    fn corrupt_ifd() {
+        let cmt2 = tiff(&[in_ifd0(Tag::DateTimeOriginal, "2020:11:27 13:34:09")]);
+        let file = cr3_boxes(&[(b"CMT2", &cmt2), (b"CMT4", b"II*\x00\xff\xff\xff\x7f")]);
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1606484049);
+
+        // with nothing else to read, the box's error is reported
+        let file = cr3_boxes(&[(b"CMT4", b"II*\x00\xff\xff\xff\x7f")]);
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::InvalidFormat(_)) => (),
+            other => panic!("Expected the CMT4 box to be invalid, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn not_cr3() {
+        assert!(!is_cr3(&ftyp(b"isom", &[b"crx "])));
+        let mut file = ftyp(b"crx ", &[b"crx "]);
+        file.extend(boxed(b"moov", &boxed(b"uuid", &[0; 16])));
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::NotFound(_)) => (),
+            other => panic!("Expected no Canon IFDs, got {:?}", other),
+        }
+    }
+}
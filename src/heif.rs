@@ -0,0 +1,524 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// HEIF (ISO/IEC 23008-12) keeps its Exif attributes in an item of type `Exif`, described in the
+// `meta` box: `iinf` gives the item's type and `iloc` where its bytes are. AVIF is HEIF with AV1
+// images, so it is read the same way.
+
+use std::convert::TryFrom;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::isobmff;
+use crate::isobmff::Boxes;
+use crate::isobmff::Bytes;
+
+// brands of HEIF files, still or sequence, HEVC or AV1 coded
+const BRANDS: [&[u8; 4]; 12] = [
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1", b"mif2", b"avif",
+    b"avis", b"miaf",
+];
+
+// a `meta` box holds item descriptions, not image data, and the Exif item is no larger than the
+// attributes and a thumbnail, so anything larger than this is corrupt
+const MAX_META_LEN: u64 = 16 * 1024 * 1024;
+
+// iloc construction methods
+const FILE_OFFSET: u64 = 0;
+const IDAT_OFFSET: u64 = 1;
+
+/// Returns whether `head`, the start of a file, is the start of a HEIF or AVIF file.
+pub(crate) fn is_heif(head: &[u8]) -> bool {
+    match isobmff::brands(head) {
+        Some(brands) => brands.iter().any(|brand| BRANDS.contains(&brand)),
+        None => false,
+    }
+}
+
+/// Reads the TIFF structure of the Exif item of the HEIF file `reader` is at the start of.
+pub(crate) fn get_exif_attr<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let start = reader.stream_position()?;
+    let meta =
+        isobmff::read_box(reader, b"meta", MAX_META_LEN)?.ok_or(exif::Error::NotFound("HEIF"))?;
+    let (_, _, meta) = isobmff::full_box(&meta).ok_or(invalid("truncated meta box"))?;
+
+    let iinf = isobmff::find_box(meta, b"iinf").ok_or(exif::Error::NotFound("HEIF"))?;
+    let item_id = find_exif_item(iinf).ok_or(exif::Error::NotFound("HEIF"))?;
+    let iloc = isobmff::find_box(meta, b"iloc").ok_or(invalid("missing iloc box"))?;
+    let location = find_location(iloc, item_id).ok_or(invalid("Exif item has no location"))?;
+
+    let mut item = Vec::new();
+    match location.construction_method {
+        FILE_OFFSET => {
+            for (offset, length) in location.extents {
+                let offset = start
+                    .checked_add(offset)
+                    .ok_or(invalid("Exif item offset overflows"))?;
+                // however many extents there are, the item they make up is read no further
+                let room = MAX_META_LEN - item.len() as u64;
+                if length > room {
+                    return Err(invalid("Exif item is too large"));
+                }
+                reader.seek(SeekFrom::Start(offset))?;
+                if length == 0 {
+                    // the extent runs to the end of the file
+                    if reader.take(room + 1).read_to_end(&mut item)? as u64 > room {
+                        return Err(invalid("Exif item is too large"));
+                    }
+                } else if (reader.take(length).read_to_end(&mut item)? as u64) < length {
+                    return Err(invalid("truncated Exif item"));
+                }
+            }
+        }
+        IDAT_OFFSET => {
+            let idat = isobmff::find_box(meta, b"idat").ok_or(invalid("missing idat box"))?;
+            for (offset, length) in location.extents {
+                let mut data = Bytes::new(idat);
+                data.take(usize::try_from(offset).map_err(|_| invalid("bad Exif offset"))?)
+                    .ok_or(invalid("truncated idat box"))?;
+                let extent = match length {
+                    0 => data.rest(),
+                    length => usize::try_from(length)
+                        .ok()
+                        .and_then(|length| data.take(length))
+                        .ok_or(invalid("truncated idat box"))?,
+                };
+                // extents may cover the same bytes, so even idat's size does not bound the item
+                if (item.len() + extent.len()) as u64 > MAX_META_LEN {
+                    return Err(invalid("Exif item is too large"));
+                }
+                item.extend_from_slice(extent);
+            }
+        }
+        _ => return Err(exif::Error::NotSupported("HEIF item construction method")),
+    }
+
+    // the item starts with the offset of the TIFF header from the end of that offset, which is
+    // usually 6 for the "Exif\0\0" prefix some writers keep from JPEG
+    let mut bytes = Bytes::new(&item);
+    let tiff_offset = bytes.u32().ok_or(invalid("truncated Exif item"))?;
+    bytes
+        .take(tiff_offset as usize)
+        .ok_or(invalid("truncated Exif item"))?;
+    Ok(bytes.rest().to_vec())
+}
+
+// Returns the id of the first item of type `Exif` in the body of an `iinf` box
+fn find_exif_item(iinf: &[u8]) -> Option<u32> {
+    let (version, _, body) = isobmff::full_box(iinf)?;
+    let mut bytes = Bytes::new(body);
+    if version == 0 {
+        bytes.u16()?;
+    } else {
+        bytes.u32()?;
+    }
+
+    for (kind, infe) in Boxes::new(bytes.rest()) {
+        if kind != *b"infe" {
+            continue;
+        }
+        // versions 0 and 1 have no item type and cannot describe an Exif item
+        let (version, _, body) = match isobmff::full_box(infe) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let mut entry = Bytes::new(body);
+        let item_id = match version {
+            2 => entry.u16().map(u32::from),
+            3 => entry.u32(),
+            _ => None,
+        };
+        entry.u16(); // item protection index
+        if let (Some(item_id), Some(b"Exif")) = (item_id, entry.array4().as_ref()) {
+            return Some(item_id);
+        }
+    }
+    None
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Location {
+    construction_method: u64,
+    // offsets and lengths, the offsets already including the base offset
+    extents: Vec<(u64, u64)>,
+}
+
+// Returns the location of item `item_id` from the body of an `iloc` box
+fn find_location(iloc: &[u8], item_id: u32) -> Option<Location> {
+    let (version, _, body) = isobmff::full_box(iloc)?;
+    let mut bytes = Bytes::new(body);
+    let sizes = bytes.u8()?;
+    let (offset_size, length_size) = (usize::from(sizes >> 4), usize::from(sizes & 0xf));
+    let sizes = bytes.u8()?;
+    let base_offset_size = usize::from(sizes >> 4);
+    let index_size = match version {
+        1 | 2 => usize::from(sizes & 0xf),
+        _ => 0,
+    };
+    let item_count = match version {
+        0 | 1 => u32::from(bytes.u16()?),
+        _ => bytes.u32()?,
+    };
+
+    for _ in 0..item_count {
+        let id = match version {
+            0 | 1 => u32::from(bytes.u16()?),
+            _ => bytes.u32()?,
+        };
+        let construction_method = match version {
+            1 | 2 => bytes.u16()? & 0xf,
+            _ => 0,
+        };
+        bytes.u16()?; // data reference index
+        let base_offset = bytes.uint(base_offset_size)?;
+        let extent_count = bytes.u16()?;
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            bytes.uint(index_size)?;
+            let offset = bytes.uint(offset_size)?;
+            let length = bytes.uint(length_size)?;
+            extents.push((base_offset.checked_add(offset)?, length));
+        }
+        if id == item_id {
+            return Some(Location {
+                construction_method: u64::from(construction_method),
+                extents,
+            });
+        }
+    }
+    None
+}
+
+fn invalid(message: &'static str) -> exif::Error {
+    exif::Error::InvalidFormat(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::isobmff::build::*;
+    use crate::tests::ascii;
+    use crate::tests::tiff;
+    use crate::DateSource;
+
+    use std::io::Cursor;
+
+    use exif::In;
+    use exif::Tag;
+
+    fn infe(item_id: u16, item_type: &[u8; 4]) -> Vec<u8> {
+        let mut body = item_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(item_type);
+        full_boxed(b"infe", 2, &body)
+    }
+
+    fn iinf(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = (entries.len() as u16).to_be_bytes().to_vec();
+        for entry in entries {
+            body.extend_from_slice(entry);
+        }
+        full_boxed(b"iinf", 0, &body)
+    }
+
+    // A version 1 iloc box with 4 byte offsets and lengths and no base offsets, locating each
+    // item with one extent
+    fn iloc(items: &[(u16, u16, u32, u32)]) -> Vec<u8> {
+        let mut body = vec![0x44, 0x00];
+        body.extend_from_slice(&(items.len() as u16).to_be_bytes());
+        for (item_id, construction_method, offset, length) in items {
+            body.extend_from_slice(&item_id.to_be_bytes());
+            body.extend_from_slice(&construction_method.to_be_bytes());
+            body.extend_from_slice(&[0, 0, 0, 1]); // data reference index, extent count
+            body.extend_from_slice(&offset.to_be_bytes());
+            body.extend_from_slice(&length.to_be_bytes());
+        }
+        full_boxed(b"iloc", 1, &body)
+    }
+
+    fn exif_item(prefix: &[u8], tiff: &[u8]) -> Vec<u8> {
+        let mut item = (prefix.len() as u32).to_be_bytes().to_vec();
+        item.extend_from_slice(prefix);
+        item.extend_from_slice(tiff);
+        item
+    }
+
+    // Builds a HEIC file with an image item and an Exif item kept in `mdat`
+    fn heic(tiff: &[u8]) -> Vec<u8> {
+        let item = exif_item(b"Exif\0\0", tiff);
+        let mut file = ftyp(b"heic", &[b"mif1", b"heic"]);
+        let meta_len = {
+            let infos = iinf(&[infe(1, b"hvc1"), infe(2, b"Exif")]);
+            let locations = iloc(&[(1, 0, 0, 0), (2, 0, 0, 0)]);
+            full_boxed(b"meta", 0, &[infos, locations].concat()).len()
+        };
+        // the Exif item follows the 8 byte header of the mdat box after meta
+        let offset = (file.len() + meta_len + 8) as u32;
+        let infos = iinf(&[infe(1, b"hvc1"), infe(2, b"Exif")]);
+        let locations = iloc(&[(1, 0, offset, 0), (2, 0, offset, item.len() as u32)]);
+        file.extend(full_boxed(b"meta", 0, &[infos, locations].concat()));
+        file.extend(boxed(b"mdat", &item));
+        file
+    }
+
+    #[test]
+    fn heic_exif() {
+        let tiff = tiff(&[ascii(
+            Tag::DateTimeOriginal,
+            In::PRIMARY,
+            "2008:05:30 15:56:01",
+        )]);
+        let file = heic(&tiff);
+        assert!(is_heif(&file));
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), tiff);
+
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+
+        // the offsets in iloc are from the start of the file, not of whatever reader holds it
+        let mut padded = vec![0; 100];
+        padded.extend_from_slice(&file);
+        let mut reader = Cursor::new(&padded);
+        reader.set_position(100);
+        let date = crate::try_get_image_date_from_reader(&mut reader).unwrap();
+        assert_eq!(date.timestamp, 1212162961);
+    }
+
+    #[test]
+    fn movie_boxes() {
+        let tiff = tiff(&[ascii(
+            Tag::DateTimeOriginal,
+            In::PRIMARY,
+            "2008:05:30 15:56:01",
+        )]);
+        let mut file = heic(&tiff);
+        // claim a movie brand too, as ISO base media files commonly do
+        let at = file.windows(4).position(|brand| brand == b"mif1").unwrap();
+        file[at..at + 4].copy_from_slice(b"iso8");
+        assert!(crate::quicktime::is_quicktime(&file));
+        // a movie box, which is not read for a movie's dates
+        let mut moov = crate::quicktime::build::header(b"mvhd", 0, 3_295_007_761);
+        moov.extend(crate::quicktime::build::keyed(
+            "com.apple.quicktime.creationdate",
+            "2001-02-03T04:05:06+0100",
+        ));
+        file.extend(boxed(b"moov", &moov));
+
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+
+        let path = crate::tests::write_temp("movie-boxes.heic", &file);
+        let candidates = crate::all_image_dates(&path).unwrap();
+        assert!(candidates
+            .iter()
+            .all(|candidate| !crate::QUICKTIME_SOURCES.contains(&candidate.source)));
+    }
+
+    #[test]
+    fn avif_exif() {
+        // AVIF encoders tend to keep the Exif item in idat, without the JPEG prefix
+        let tiff = tiff(&[ascii(Tag::DateTime, In::PRIMARY, "2021:02:03 04:05:06")]);
+        let mut idat = vec![0xaa; 10]; // some other item
+        idat.extend(exif_item(b"", &tiff));
+        let infos = iinf(&[infe(1, b"av01"), infe(7, b"Exif")]);
+        let locations = iloc(&[(7, 1, 10, 0)]);
+        let meta = [infos, locations, boxed(b"idat", &idat)].concat();
+
+        let mut file = ftyp(b"avif", &[b"mif1", b"miaf"]);
+        file.extend(boxed(b"free", &[0; 3]));
+        file.extend(full_boxed(b"meta", 0, &meta));
+        assert!(is_heif(&file));
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), tiff);
+
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+        assert_eq!(date.timestamp, 1612325106);
+    }
+
+    #[test]
+    fn heic_sample() {
+        // written by libheif, so not built on any assumption of ours; its iloc box is version 0
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/exif.heic");
+        let file = std::fs::read(path).unwrap();
+        assert!(is_heif(&file));
+        let exif = exif::Reader::new()
+            .read_raw(get_exif_attr(&mut Cursor::new(&file)).unwrap())
+            .unwrap();
+        let software = exif.get_field(Tag::Software, In::PRIMARY).unwrap();
+        assert_eq!(
+            software.display_value().to_string(),
+            "\"libheif + kamadak-exif\""
+        );
+    }
+
+    #[test]
+    fn camera_samples() {
+        // an iPhone 14 Pro photo, with the offset and fractional seconds it records
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/iphone.heic");
+        let date = crate::try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1693722494);
+        assert_eq!(date.date_time.to_rfc3339(), "2023-09-03T09:28:14.307+03:00");
+
+        // written by ravif, which splits the Exif item in two extents: its offset and the TIFF data
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/exif.avif");
+        let date = crate::try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1623515045);
+        assert_eq!(date.date_time.to_rfc3339(), "2021-06-12T18:24:05+02:00");
+    }
+
+    #[test]
+    fn iloc_versions() {
+        // version 0: 4 byte offsets and lengths, 2 byte base offsets, 16 bit item ids and no
+        // construction method
+        #[rustfmt::skip]
+        let iloc = [
+            0, 0, 0, 0, // version, flags
+            0x44, 0x20, // offset and length sizes, base offset size
+            0, 2, // item count
+            0, 1, 0, 0, 0x01, 0x00, 0, 1, // item 1 at base 256, one extent
+            0, 0, 0, 0, 0, 0, 0, 9,
+            0, 2, 0, 0, 0x10, 0x00, 0, 2, // item 2 at base 4096, two extents
+            0, 0, 0, 8, 0, 0, 0, 20,
+            0, 0, 0, 40, 0, 0, 0, 12,
+        ];
+        assert_eq!(
+            find_location(&iloc, 2),
+            Some(Location {
+                construction_method: FILE_OFFSET,
+                extents: vec![(4104, 20), (4136, 12)],
+            })
+        );
+
+        // version 2: 8 byte offsets, 2 byte lengths, 4 byte base offsets and indexes, 32 bit item
+        // ids and item count
+        #[rustfmt::skip]
+        let iloc = [
+            2, 0, 0, 0,
+            0x82, 0x44,
+            0, 0, 0, 1,
+            0, 1, 0, 1, 0, 1, 0, 0, // item 65537, constructed from idat
+            0, 0, 0, 100, 0, 3, // base 100, three extents
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 5,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 10, 0, 5,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 20, 0, 0,
+        ];
+        assert_eq!(
+            find_location(&iloc, 65537),
+            Some(Location {
+                construction_method: IDAT_OFFSET,
+                extents: vec![(101, 5), (110, 5), (120, 0)],
+            })
+        );
+        assert_eq!(find_location(&iloc, 1), None);
+    }
+
+    #[test]
+    fn exif_in_extents() {
+        // the Exif item split in two extents of mdat, located from a base offset
+        let tiff = tiff(&[ascii(Tag::DateTime, In::PRIMARY, "2021:02:03 04:05:06")]);
+        let item = exif_item(b"Exif\0\0", &tiff);
+        let (first, second) = item.split_at(7);
+        let mut mdat = first.to_vec();
+        mdat.extend_from_slice(b"image data");
+        mdat.extend_from_slice(second);
+
+        let infos = iinf(&[infe(5, b"Exif")]);
+        let mut body = vec![0x44, 0x40, 0, 1, 0, 5, 0, 0];
+        let base_offset = body.len(); // patched below, once the file's layout is known
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(&[0, 2]);
+        body.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 7]);
+        body.extend_from_slice(&[0, 0, 0, 17]);
+        body.extend_from_slice(&(second.len() as u32).to_be_bytes());
+        let meta = |iloc: &[u8]| {
+            full_boxed(
+                b"meta",
+                0,
+                &[infos.clone(), full_boxed(b"iloc", 0, iloc)].concat(),
+            )
+        };
+
+        let mut file = ftyp(b"heic", &[b"mif1", b"heic"]);
+        let mdat_start = (file.len() + meta(&body).len() + 8) as u32;
+        body[base_offset..base_offset + 4].copy_from_slice(&mdat_start.to_be_bytes());
+        file.extend(meta(&body));
+        file.extend(boxed(b"mdat", &mdat));
+
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), tiff);
+    }
+
+    #[test]
+    fn heif_without_exif() {
+        let infos = iinf(&[infe(1, b"hvc1")]);
+        let locations = iloc(&[(1, 0, 0, 0)]);
+        let mut file = ftyp(b"heic", &[b"mif1"]);
+        file.extend(full_boxed(b"meta", 0, &[infos, locations].concat()));
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::NotFound(_)) => (),
+            other => panic!("Expected no Exif item, got {:?}", other),
+        }
+
+        // a location past the end of the file
+        let infos = iinf(&[infe(2, b"Exif")]);
+        let locations = iloc(&[(2, 0, 5000, 100)]);
+        let mut file = ftyp(b"heic", &[b"mif1"]);
+        file.extend(full_boxed(b"meta", 0, &[infos, locations].concat()));
+        assert!(get_exif_attr(&mut Cursor::new(&file)).is_err());
+
+        assert!(!is_heif(&ftyp(b"isom", &[b"mp41"])));
+    }
+
+    #[test]
+    fn exif_item_too_large() {
+        // one extent longer than any Exif item, which is not read at all
+        let infos = iinf(&[infe(2, b"Exif")]);
+        let locations = iloc(&[(2, 0, 0, u32::MAX)]);
+        let mut file = ftyp(b"heic", &[b"mif1"]);
+        file.extend(full_boxed(b"meta", 0, &[infos, locations].concat()));
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::InvalidFormat(message)) => {
+                assert_eq!(message, "Exif item is too large")
+            }
+            other => panic!("Expected an oversized item, got {:?}", other),
+        }
+
+        // a small idat repeated by many extents
+        let idat = vec![0; 64 * 1024];
+        let extents = 300u16;
+        let mut body = vec![0x44, 0x00, 0, 1, 0, 3, 0, 1, 0, 0];
+        body.extend_from_slice(&extents.to_be_bytes());
+        for _ in 0..extents {
+            body.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        let meta = [
+            iinf(&[infe(3, b"Exif")]),
+            full_boxed(b"iloc", 1, &body),
+            boxed(b"idat", &idat),
+        ]
+        .concat();
+        let mut file = ftyp(b"avif", &[b"mif1"]);
+        file.extend(full_boxed(b"meta", 0, &meta));
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::InvalidFormat(message)) => {
+                assert_eq!(message, "Exif item is too large")
+            }
+            other => panic!("Expected an oversized item, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// IPTC IIM (Information Interchange Model) records reach JPEG files inside the image resource
+// blocks Photoshop writes to an APP13 segment, as resource 0x0404.
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+
+use crate::isobmff::Bytes;
+use crate::jpeg;
+
+const APP13: u8 = 0xed;
+const PHOTOSHOP_ID: &[u8] = b"Photoshop 3.0\0";
+const IIM_RESOURCE: u16 = 0x0404;
+
+// the signatures of image resource blocks; anything but 8BIM is from long gone versions
+const SIGNATURES: [&[u8; 4]; 4] = [b"8BIM", b"PHUT", b"AgHg", b"DCSR"];
+
+/// Record 2, dataset 55: the date the content was created, "CCYYMMDD".
+pub(crate) const DATE_CREATED: (u8, u8) = (2, 55);
+/// Record 2, dataset 60: the time the content was created, "HHMMSS±HHMM".
+pub(crate) const TIME_CREATED: (u8, u8) = (2, 60);
+
+/// Reads the IIM records of the JPEG file `reader` is at the start of.
+pub(crate) fn get_iim<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let segments = jpeg::read_segments(reader, &[APP13])?;
+    Ok(segments.into_iter().find_map(|(_, data)| {
+        let resources = data.strip_prefix(PHOTOSHOP_ID)?;
+        find_resource(resources, IIM_RESOURCE).map(|iim| iim.to_vec())
+    }))
+}
+
+// Returns the data of the image resource `id`
+fn find_resource(resources: &[u8], id: u16) -> Option<&[u8]> {
+    let mut bytes = Bytes::new(resources);
+    loop {
+        let signature = bytes.array4()?;
+        if !SIGNATURES.contains(&&signature) {
+            return None;
+        }
+        let found = bytes.u16()?;
+        // a Pascal string, padded to an even length, length byte included
+        let name_len = usize::from(bytes.u8()?);
+        bytes.take(name_len + (name_len + 1) % 2)?;
+        let len = usize::try_from(bytes.u32()?).ok()?;
+        let data = bytes.take(len)?;
+        if found == id {
+            return Some(data);
+        }
+        if len % 2 == 1 {
+            bytes.take(1)?;
+        }
+    }
+}
+
+/// Returns the value of the first dataset `(record, dataset)` in `iim`.
+pub(crate) fn get_dataset(iim: &[u8], (record, dataset): (u8, u8)) -> Option<&[u8]> {
+    let mut bytes = Bytes::new(iim);
+    loop {
+        // every dataset starts with a tag marker
+        if bytes.u8()? != 0x1c {
+            return None;
+        }
+        let found = (bytes.u8()?, bytes.u8()?);
+        let len = match bytes.u16()? {
+            // an extended dataset, whose length is in the number of bytes given by the rest
+            len if len & 0x8000 != 0 => bytes.uint(usize::from(len & 0x7fff))?,
+            len => u64::from(len),
+        };
+        let value = bytes.take(usize::try_from(len).ok()?)?;
+        if found == (record, dataset) {
+            return Some(value);
+        }
+    }
+}
+
+/// Builds IIM records for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    pub(crate) fn dataset((record, dataset): (u8, u8), value: &[u8]) -> Vec<u8> {
+        let mut data = vec![0x1c, record, dataset];
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
+    // An APP13 segment holding an unrelated resource and then the IIM records
+    pub(crate) fn app13(iim: &[u8]) -> Vec<u8> {
+        let mut data = super::PHOTOSHOP_ID.to_vec();
+        data.extend_from_slice(b"8BIM\x03\xed\x00\x00\x00\x00\x00\x03abc\x00");
+        data.extend_from_slice(b"8BIM\x04\x04\x03IIM");
+        data.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+        data.extend_from_slice(iim);
+        crate::jpeg::build::segment(super::APP13, &data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn iim_datasets() {
+        let mut iim = dataset((1, 90), b"\x1b%G");
+        iim.extend(dataset(DATE_CREATED, b"20080530"));
+        // an extended dataset, with a two byte length
+        iim.extend_from_slice(&[0x1c, 2, 120, 0x80, 0x02, 0x00, 0x03]);
+        iim.extend_from_slice(b"abc");
+        iim.extend(dataset(TIME_CREATED, b"155601+0900"));
+
+        let file = jpeg::build::jpeg(&[app13(&iim)]);
+        let read = get_iim(&mut Cursor::new(&file)).unwrap().unwrap();
+        assert_eq!(read, iim);
+        assert_eq!(get_dataset(&iim, DATE_CREATED), Some(&b"20080530"[..]));
+        assert_eq!(get_dataset(&iim, TIME_CREATED), Some(&b"155601+0900"[..]));
+        assert_eq!(get_dataset(&iim, (2, 120)), Some(&b"abc"[..]));
+        assert_eq!(get_dataset(&iim, (2, 62)), None);
+
+        let file = jpeg::build::jpeg(&[jpeg::build::segment(APP13, b"Photoshop 3.0\0")]);
+        assert_eq!(get_iim(&mut Cursor::new(&file)).unwrap(), None);
+    }
+}
@@ -0,0 +1,253 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Walking the boxes of ISO base media files (ISO/IEC 14496-12), the container HEIF, AVIF and MP4
+// are built on.
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::invalid_data;
+
+/// A big endian cursor over a byte slice. Every read returns `None` rather than running past the
+/// end, so truncated input cannot cause a panic.
+#[derive(Debug, Clone)]
+pub(crate) struct Bytes<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Bytes<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Bytes<'a> {
+        Bytes { data }
+    }
+
+    /// The bytes not read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.data.len() {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.uint(2).map(|n| n as u16)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.uint(4).map(|n| n as u32)
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.uint(8)
+    }
+
+    pub(crate) fn array4(&mut self) -> Option<[u8; 4]> {
+        self.take(4).map(|b| [b[0], b[1], b[2], b[3]])
+    }
+
+    /// Reads an unsigned integer of `len` bytes, up to 8. A length of 0 reads nothing and is 0.
+    pub(crate) fn uint(&mut self, len: usize) -> Option<u64> {
+        if len > 8 {
+            return None;
+        }
+        let bytes = self.take(len)?;
+        Some(bytes.iter().fold(0, |n, &b| n << 8 | u64::from(b)))
+    }
+}
+
+/// Iterates over the boxes in `data`, yielding each one's type and body. Stops at the first box
+/// whose size does not fit.
+#[derive(Debug, Clone)]
+pub(crate) struct Boxes<'a> {
+    data: Bytes<'a>,
+}
+
+impl<'a> Boxes<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Boxes<'a> {
+        Boxes {
+            data: Bytes::new(data),
+        }
+    }
+}
+
+impl<'a> Iterator for Boxes<'a> {
+    type Item = ([u8; 4], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let size = self.data.u32()?;
+        let kind = self.data.array4()?;
+        let body_len = match size {
+            // the box extends to the end of its parent
+            0 => self.data.rest().len(),
+            1 => usize::try_from(self.data.u64()?.checked_sub(16)?).ok()?,
+            size => usize::try_from(size.checked_sub(8)?).ok()?,
+        };
+        match self.data.take(body_len) {
+            Some(body) => Some((kind, body)),
+            None => {
+                // do not yield anything past a corrupt box
+                self.data = Bytes::new(&[]);
+                None
+            }
+        }
+    }
+}
+
+/// Returns the body of the first box of type `kind` in `data`.
+pub(crate) fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    Boxes::new(data)
+        .find(|(found, _)| found == kind)
+        .map(|(_, body)| body)
+}
+
+/// Splits the body of a full box into its version, flags and the rest.
+pub(crate) fn full_box(body: &[u8]) -> Option<(u8, u32, &[u8])> {
+    let mut bytes = Bytes::new(body);
+    let version = bytes.u8()?;
+    let flags = bytes.uint(3)? as u32;
+    Some((version, flags, bytes.rest()))
+}
+
+/// Returns the major and compatible brands of the file type box that starts `head`, or `None` if
+/// `head` is not the start of an ISO base media file.
+pub(crate) fn brands(head: &[u8]) -> Option<Vec<[u8; 4]>> {
+    let mut bytes = Bytes::new(head);
+    let size = bytes.u32()?;
+    if bytes.array4()? != *b"ftyp" || size < 16 {
+        return None;
+    }
+
+    // the box may be longer than what was read of the file so far
+    let mut body = Bytes::new(bytes.take((size as usize - 8).min(bytes.rest().len()))?);
+    let mut brands = vec![body.array4()?];
+    body.u32()?; // minor version
+    while let Some(brand) = body.array4() {
+        brands.push(brand);
+    }
+    Some(brands)
+}
+
+/// Reads the body of the first top level box of type `kind`, seeking over the others. Gives up
+/// with an error if the body is larger than `max_len`, so a corrupt size cannot exhaust memory.
+pub(crate) fn read_box<R: Read + Seek>(
+    reader: &mut R,
+    kind: &[u8; 4],
+    max_len: u64,
+) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let mut bytes = Bytes::new(&header);
+        let size = bytes.u32().unwrap_or(0);
+        let found = bytes.array4().unwrap_or_default();
+
+        let body_len = match size {
+            0 if found == *kind => Some(max_len),
+            0 => return Ok(None), // the last box in the file, and not the one we want
+            1 => {
+                let mut large = [0; 8];
+                reader.read_exact(&mut large)?;
+                u64::from_be_bytes(large).checked_sub(16)
+            }
+            size => u64::from(size).checked_sub(8),
+        };
+        let body_len = body_len.ok_or_else(|| invalid_data("box is smaller than its header"))?;
+
+        if found == *kind {
+            if body_len > max_len && size != 0 {
+                return Err(invalid_data("box is too large"));
+            }
+            let mut body = Vec::new();
+            reader.take(body_len).read_to_end(&mut body)?;
+            return Ok(Some(body));
+        }
+        let skip = i64::try_from(body_len).map_err(|_| invalid_data("box is too large"))?;
+        reader.seek(SeekFrom::Current(skip))?;
+    }
+}
+
+/// Builds boxes for the tests of the formats built on ISO base media files.
+#[cfg(test)]
+pub(crate) mod build {
+    pub(crate) fn boxed(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    pub(crate) fn full_boxed(kind: &[u8; 4], version: u8, body: &[u8]) -> Vec<u8> {
+        let mut full = vec![version, 0, 0, 0];
+        full.extend_from_slice(body);
+        boxed(kind, &full)
+    }
+
+    pub(crate) fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut body = major.to_vec();
+        body.extend_from_slice(&[0; 4]);
+        for brand in compatible {
+            body.extend_from_slice(*brand);
+        }
+        boxed(b"ftyp", &body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn walk_boxes() {
+        let mut data = boxed(b"free", &[1, 2, 3]);
+        data.extend(boxed(b"meta", &boxed(b"hdlr", b"pict")));
+        data.extend_from_slice(&[0, 0, 0, 99, b'b', b'a', b'd']); // truncated
+
+        let kinds: Vec<[u8; 4]> = Boxes::new(&data).map(|(kind, _)| kind).collect();
+        assert_eq!(kinds, vec![*b"free", *b"meta"]);
+        let meta = find_box(&data, b"meta").unwrap();
+        assert_eq!(find_box(meta, b"hdlr"), Some(&b"pict"[..]));
+
+        let mut reader = Cursor::new(&data);
+        let meta = read_box(&mut reader, b"meta", 1024).unwrap();
+        assert_eq!(meta, Some(boxed(b"hdlr", b"pict")));
+        let mut reader = Cursor::new(&data);
+        assert!(read_box(&mut reader, b"meta", 4).is_err());
+    }
+
+    #[test]
+    fn file_type_brands() {
+        let head = ftyp(b"heic", &[b"mif1", b"heic"]);
+        assert_eq!(brands(&head), Some(vec![*b"heic", *b"mif1", *b"heic"]));
+        assert_eq!(brands(b"\xff\xd8\xff\xe1"), None);
+    }
+}
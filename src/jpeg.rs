@@ -0,0 +1,156 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// JPEG keeps its metadata in application segments ahead of the image data: Exif and XMP in APP1
+// segments, told apart by the identifier they start with, and IPTC in APP13.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::invalid_data;
+
+pub(crate) const APP1: u8 = 0xe1;
+
+const XMP_ID: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Returns whether `head`, the start of a file, is the start of a JPEG file.
+pub(crate) fn is_jpeg(head: &[u8]) -> bool {
+    head.starts_with(&[0xff, 0xd8])
+}
+
+/// Reads the XMP packet of the JPEG file `reader` is at the start of.
+pub(crate) fn get_xmp<R: Read + Seek>(reader: &mut R) -> io::Result<Option<String>> {
+    let segments = read_segments(reader, &[APP1])?;
+    Ok(segments.into_iter().find_map(|(_, data)| {
+        let packet = data.strip_prefix(XMP_ID)?;
+        Some(String::from_utf8_lossy(packet).into_owned())
+    }))
+}
+
+/// Reads the marker and data of every segment with one of the given markers, seeking over the
+/// others, up to the start of the image data.
+pub(crate) fn read_segments<R: Read + Seek>(
+    reader: &mut R,
+    markers: &[u8],
+) -> io::Result<Vec<(u8, Vec<u8>)>> {
+    let mut soi = [0; 2];
+    reader.read_exact(&mut soi)?;
+    if !is_jpeg(&soi) {
+        return Err(invalid_data("not a JPEG file"));
+    }
+
+    let mut segments = Vec::new();
+    loop {
+        let marker = match next_marker(reader) {
+            Ok(marker) => marker,
+            // a file cut short after its metadata still has the metadata
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        };
+        match marker {
+            // start of scan, after which comes the image data, and end of image
+            0xda | 0xd9 => break,
+            // markers that stand alone, without a length
+            0x01 | 0xd0..=0xd7 => continue,
+            _ => (),
+        }
+
+        let mut len = [0; 2];
+        if reader.read_exact(&mut len).is_err() {
+            break;
+        }
+        let len = u16::from_be_bytes(len)
+            .checked_sub(2)
+            .ok_or_else(|| invalid_data("segment is smaller than its length"))?;
+        if markers.contains(&marker) {
+            let mut data = Vec::new();
+            reader.take(u64::from(len)).read_to_end(&mut data)?;
+            if data.len() < usize::from(len) {
+                break;
+            }
+            segments.push((marker, data));
+        } else {
+            reader.seek(SeekFrom::Current(i64::from(len)))?;
+        }
+    }
+    Ok(segments)
+}
+
+// Reads the next marker, skipping the fill bytes that may precede it
+fn next_marker<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte)?;
+    if byte[0] != 0xff {
+        return Err(invalid_data("expected a JPEG marker"));
+    }
+    while byte[0] == 0xff {
+        reader.read_exact(&mut byte)?;
+    }
+    Ok(byte[0])
+}
+
+/// Builds JPEG files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    pub(crate) fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    // A JPEG file with the given segments ahead of some image data
+    pub(crate) fn jpeg(segments: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0xff, 0xd8];
+        for segment in segments {
+            file.extend_from_slice(segment);
+        }
+        file.extend(segment(0xda, &[0; 10]));
+        file.extend_from_slice(&[0x12, 0xff, 0x00, 0x34, 0xff, 0xd9]);
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn xmp_segment() {
+        let mut xmp = XMP_ID.to_vec();
+        xmp.extend_from_slice(b"<x:xmpmeta/>");
+        let file = jpeg(&[
+            segment(0xe0, b"JFIF\0\x01\x02"),
+            segment(APP1, b"Exif\0\0MM\0\x2a"),
+            vec![0xff, 0xff], // fill
+            segment(APP1, &xmp),
+        ]);
+        assert!(is_jpeg(&file));
+        let packet = get_xmp(&mut Cursor::new(&file)).unwrap();
+        assert_eq!(packet.as_deref(), Some("<x:xmpmeta/>"));
+
+        let file = jpeg(&[segment(APP1, b"Exif\0\0MM\0\x2a")]);
+        assert_eq!(get_xmp(&mut Cursor::new(&file)).unwrap(), None);
+
+        // cut short in the middle of a segment
+        let mut file = jpeg(&[segment(0xe2, &[0; 40]), segment(APP1, &xmp)]);
+        file.truncate(30);
+        let segments = read_segments(&mut Cursor::new(&file), &[APP1]).unwrap();
+        assert!(segments.is_empty());
+    }
+}
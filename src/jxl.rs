@@ -0,0 +1,93 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// A JPEG XL file is either a bare codestream, which has no room for metadata, or an ISO base media
+// file starting with a signature box. The latter keeps Exif in an `Exif` box, whose body starts
+// with the offset of the TIFF header within the rest of it. Boxes compressed with Brotli (`brob`)
+// are not read.
+
+use std::io::Read;
+use std::io::Seek;
+
+use crate::isobmff;
+use crate::isobmff::Bytes;
+
+const SIGNATURE: &[u8; 12] = b"\0\0\0\x0cJXL \r\n\x87\n";
+
+// the Exif attributes, and at most a thumbnail, so anything larger is corrupt
+const MAX_EXIF_LEN: u64 = 16 * 1024 * 1024;
+
+/// Returns whether `head`, the start of a file, is the start of a JPEG XL container.
+pub(crate) fn is_jxl(head: &[u8]) -> bool {
+    head.starts_with(SIGNATURE)
+}
+
+/// Reads the Exif attributes of the JPEG XL container `reader` is at the start of.
+pub(crate) fn get_exif_attr<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let body = isobmff::read_box(reader, b"Exif", MAX_EXIF_LEN)?
+        .ok_or(exif::Error::NotFound("JPEG XL"))?;
+    let mut bytes = Bytes::new(&body);
+    let offset = bytes.u32().ok_or(invalid("truncated Exif box"))?;
+    let tiff = bytes
+        .rest()
+        .get(offset as usize..)
+        .ok_or(invalid("Exif offset is out of range"))?;
+    Ok(tiff.to_vec())
+}
+
+fn invalid(message: &'static str) -> exif::Error {
+    exif::Error::InvalidFormat(message)
+}
+
+/// Builds JPEG XL files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    use crate::isobmff::build::*;
+
+    // A JPEG XL container whose Exif box holds `tiff` after `skipped` bytes of padding
+    pub(crate) fn jxl(tiff: &[u8], skipped: usize) -> Vec<u8> {
+        let mut exif = (skipped as u32).to_be_bytes().to_vec();
+        exif.resize(4 + skipped, 0);
+        exif.extend_from_slice(tiff);
+
+        let mut file = super::SIGNATURE.to_vec();
+        file.extend(ftyp(b"jxl ", &[b"jxl "]));
+        file.extend(boxed(b"jxlc", &[0xff, 0x0a, 0, 0]));
+        file.extend(boxed(b"Exif", &exif));
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn exif_offset() {
+        let file = jxl(b"MM\0\x2a", 6);
+        assert!(is_jxl(&file));
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), b"MM\0\x2a");
+
+        assert!(!is_jxl(&[0xff, 0x0a, 0, 0]));
+        let mut file = jxl(b"", 0);
+        let len = file.len();
+        file[len - 4..].copy_from_slice(&[0, 0, 0, 9]);
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::InvalidFormat(_)) => (),
+            other => panic!("Expected an invalid offset, got {:?}", other),
+        }
+    }
+}
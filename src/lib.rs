@@ -11,106 +11,1087 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+mod cr3;
+mod heif;
+mod iptc;
+mod isobmff;
+mod jpeg;
+mod jxl;
+mod parse;
+mod pattern;
+mod png;
+mod policy;
+mod quicktime;
+mod raf;
+mod tiff;
+// tzf-rs already needs a newer Rust than the rest of the crate
+#[cfg(feature = "tz-lookup")]
+#[clippy::msrv = "1.88"]
+mod tz;
+mod util;
+mod webp;
+mod x3f;
+mod xmp;
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
+use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
 use exif::In;
 use exif::Reader;
 
-use chrono::NaiveDateTime;
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use chrono::Offset;
+use chrono::TimeZone;
+use chrono::Timelike;
+use chrono::Utc;
 
-const EXIF_DATE_TIME_ORIGINAL: u8 = 1;
-const EXIF_CREATE_DATE: u8 = 2;
-const EXIF_MODIFY_DATE: u8 = 3;
+use parse::Lenient;
+use parse::TextDate;
 
-const SYS_CREATED: u8 = 4;
-const SYS_MODIFIED: u8 = 5;
-const SYS_ACCESSED: u8 = 6;
+pub use policy::DatePolicy;
 
-const ERR_DATE: u64 = 1936268400;
+const ERR_DATE: i64 = 1936268400;
 
-pub fn get_image_date(filename: &str) -> u64 {
-    // the first step is to see if we can even open the file...
-    let file = match File::open(&filename) {
-        Ok(file) => file,
-        Err(_) => return ERR_DATE,
-    };
+// how much of the start of a file to look at when telling its format
+const HEAD_LEN: usize = 4096;
 
-    // now create a vector to hold all of the dates we hope we can find
-    let mut dates: Vec<(u8, u64)> = Vec::new();
-    get_exif_image_dates(&file, &mut dates);
-    if dates.len() == 0 {
-        // exif data has a higher priority, so we do not need to try here unless we could not
-        // extract any exif data
-        get_filesystem_dates(&file, &mut dates);
-    }
-
-    // sort the vector so we can return the first (and highest priority)
-    if dates.len() > 0 {
-        // sort the vector by the first element in each tuple, i.e. the priority
-        dates.sort_by(|a, b| a.0.cmp(&b.0));
-        dates[0].1
-    } else {
+pub(crate) const EXIF_SOURCES: [DateSource; 4] = [
+    DateSource::ExifDateTimeOriginal,
+    DateSource::ExifGps,
+    DateSource::ExifCreateDate,
+    DateSource::ExifModifyDate,
+];
+
+const QUICKTIME_SOURCES: [DateSource; 3] = [
+    DateSource::QuickTimeCreationDate,
+    DateSource::QuickTimeDay,
+    DateSource::QuickTimeCreateDate,
+];
+
+/// Where a date was found. The variants are declared in the default order of priority, highest
+/// first, which a `DatePolicy` can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DateSource {
+    /// Exif DateTimeOriginal (0x9003), when the image was taken.
+    ExifDateTimeOriginal,
+    /// Exif GPSDateStamp (0x001d) and GPSTimeStamp (0x0007), the UTC time of the satellite fix.
+    ExifGps,
+    /// Exif CreateDate (0x9004, called DateTimeDigitized by the Exif spec.), when the image was
+    /// digitized.
+    ExifCreateDate,
+    /// Exif ModifyDate (0x0132, called DateTime by the Exif spec.), when the file was last changed.
+    ExifModifyDate,
+    /// An XMP sidecar file next to the image, e.g. "IMG_1234.CR2.xmp" or "IMG_1234.xmp", as
+    /// written by Lightroom, darktable and digiKam. Its exif:DateTimeOriginal is preferred, then
+    /// xmp:CreateDate, then photoshop:DateCreated.
+    XmpSidecar,
+    /// The XMP packet embedded in the image: a JPEG APP1 segment, TIFF tag 700 (XMLPacket), a PNG
+    /// iTXt chunk with the keyword "XML:com.adobe.xmp" or a WebP "XMP " chunk. The same properties
+    /// are read as from a sidecar.
+    XmpEmbedded,
+    /// IPTC IIM DateCreated (2:55) and TimeCreated (2:60), from the Photoshop image resources in a
+    /// JPEG APP13 segment.
+    IptcDateCreated,
+    /// The "com.apple.quicktime.creationdate" metadata item of a QuickTime movie, the local time a
+    /// video was recorded, with its offset.
+    QuickTimeCreationDate,
+    /// The `©day` user data atom of a QuickTime, MP4 or 3GP movie.
+    QuickTimeDay,
+    /// The creation time in the movie header (`mvhd`) of a QuickTime, MP4 or 3GP movie, or failing
+    /// that in its first track header (`tkhd`) that has one.
+    QuickTimeCreateDate,
+    /// The "Creation Time" keyword of a PNG text chunk (tEXt, zTXt or iTXt), when the image was
+    /// created.
+    PngCreationTime,
+    /// The TIME property of a Sigma X3F file, when the image was captured, in seconds since the Unix
+    /// epoch.
+    X3fTime,
+    /// A date in the file's name, such as `IMG_20200101_123456.jpg` or `IMG-20200101-WA0001.jpg`,
+    /// which survives messenger apps stripping the metadata. Matched against the patterns
+    /// registered with `DatePolicy::filename_pattern`, then the built-in ones for common cameras,
+    /// phones and apps. Only read for files given by path.
+    FileName,
+    /// A date in the names of the directories the file is in, nearest first, such as
+    /// `2009/2009-07-14 Wedding/`, `2009/07/14/` or `Photos/1998/Summer/`, as archives are often
+    /// organised. Usually only the year, month or day. Only read for files given by path, and only
+    /// from the four directories nearest the file that the path names, so a relative path is not
+    /// resolved against the current directory.
+    DirectoryName,
+    /// The filesystem creation time.
+    SysCreated,
+    /// The filesystem modification time.
+    SysModified,
+    /// The filesystem access time.
+    SysAccessed,
+}
+
+/// The date an image was taken, as determined by `try_get_image_date`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageDate {
+    /// Where the date was found.
+    pub source: DateSource,
+    /// Seconds since the Unix epoch, negative for dates before 1970.
+    pub timestamp: i64,
+    /// The date in the offset it was recorded in, or in UTC when the source does not say. Unlike
+    /// `timestamp` this keeps any fraction of a second the source records, so it can be used to
+    /// order images taken within the same second.
+    pub date_time: DateTime<FixedOffset>,
+    /// The offset from UTC the date was recorded in, if the source says.
+    pub offset: Option<FixedOffset>,
+    /// Whether `offset` was not recorded but inferred, e.g. by comparing a camera time with a GPS
+    /// time.
+    pub offset_inferred: bool,
+    /// The fractional part of the second, in nanoseconds, if the source records it.
+    pub subsec_nanos: Option<u32>,
+    /// The value the date was parsed from, for sources that store dates as text.
+    pub raw: Option<String>,
+    /// Whether an Exif date was found in the thumbnail directory (IFD1) rather than the primary
+    /// image's.
+    pub thumbnail: bool,
+    /// Whether `raw` was malformed and had to be read leniently.
+    pub repaired: bool,
+    /// How much of the date was recorded.
+    pub precision: Precision,
+}
+
+impl ImageDate {
+    fn new(
+        source: DateSource,
+        date_time: DateTime<FixedOffset>,
+        offset: Option<FixedOffset>,
+    ) -> ImageDate {
+        ImageDate {
+            source,
+            timestamp: date_time.timestamp(),
+            date_time,
+            offset,
+            offset_inferred: false,
+            subsec_nanos: None,
+            raw: None,
+            thumbnail: false,
+            repaired: false,
+            precision: Precision::Second,
+        }
+    }
+
+    /// Reinterprets a naive time as having been recorded in `offset`.
+    fn set_inferred_offset(&mut self, offset: FixedOffset) {
+        let local = self.date_time.naive_local();
+        if let Some(date_time) = offset.from_local_datetime(&local).single() {
+            self.timestamp = date_time.timestamp();
+            self.date_time = date_time;
+            self.offset = Some(offset);
+            self.offset_inferred = true;
+        }
+    }
+
+    /// Returns `None` for times too far from the epoch to be represented.
+    fn from_system_time(source: DateSource, time: SystemTime) -> Option<ImageDate> {
+        // files can legitimately carry times before the epoch, e.g. a scan whose time was set to
+        // when the photo was taken, so both directions are handled
+        let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => (i64::try_from(after.as_secs()).ok()?, after.subsec_nanos()),
+            Err(err) => {
+                let before = err.duration();
+                let secs = i64::try_from(before.as_secs()).ok()?;
+                match before.subsec_nanos() {
+                    0 => (-secs, 0),
+                    nanos => (-secs - 1, 1_000_000_000 - nanos),
+                }
+            }
+        };
+        let date_time = Utc.timestamp_opt(secs, nanos).single()?;
+        let mut image_date = ImageDate::new(source, date_time.fixed_offset(), None);
+        image_date.subsec_nanos = Some(nanos);
+        Some(image_date)
+    }
+}
+
+/// How much of a date its source recorded, coarsest first. Fields that were not recorded are taken
+/// to be at their start, so a date of `Month` precision is the first of that month at midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Precision {
+    /// Only the year.
+    Year,
+    /// The year and month.
+    Month,
+    /// The date, but not the time.
+    Day,
+    /// The date and hour.
+    Hour,
+    /// The date, hour and minute.
+    Minute,
+    /// Whole seconds or better, which every source but free-form text always records.
+    Second,
+}
+
+/// A date found by `all_image_dates`, whether or not it could be parsed.
+#[derive(Debug)]
+pub struct DateCandidate {
+    /// Where the date was found.
+    pub source: DateSource,
+    /// The name of the tag or field within the source, e.g. "DateTimeOriginal" or "modified".
+    pub tag: String,
+    /// The date, or why it could not be parsed.
+    pub date: Result<ImageDate, ImageDateError>,
+}
+
+/// The reasons `try_get_image_date` can fail to determine a date.
+#[derive(Debug)]
+pub enum ImageDateError {
+    /// The file could not be opened or read.
+    Io(io::Error),
+    /// The file is not a container Exif data can be read from, or its Exif data is corrupt.
+    UnreadableContainer(exif::Error),
+    /// A date field was present, but its value could not be parsed. Holds the offending value.
+    MalformedDate(String),
+    /// None of the sources yielded a date.
+    NoSource,
+}
+
+impl fmt::Display for ImageDateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageDateError::Io(err) => write!(f, "I/O error: {}", err),
+            ImageDateError::UnreadableContainer(err) => write!(f, "unreadable container: {}", err),
+            ImageDateError::MalformedDate(value) => write!(f, "malformed date: {:?}", value),
+            ImageDateError::NoSource => write!(f, "no date source found"),
+        }
+    }
+}
+
+impl Error for ImageDateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageDateError::Io(err) => Some(err),
+            ImageDateError::UnreadableContainer(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageDateError {
+    fn from(err: io::Error) -> ImageDateError {
+        ImageDateError::Io(err)
+    }
+}
+
+/// Returns the date the image was taken in seconds since the Unix epoch, or a date in the future
+/// if it could not be determined. Use `try_get_image_date` to find out why it failed.
+///
+/// Dates before 1970 are negative. No input, however malformed, makes this panic.
+pub fn get_image_date<P: AsRef<Path>>(path: P) -> i64 {
+    get_image_date_with(path, &DatePolicy::default())
+}
+
+/// Like `get_image_date`, but with the sources and their priority decided by `policy`.
+pub fn get_image_date_with<P: AsRef<Path>>(path: P, policy: &DatePolicy) -> i64 {
+    or_err_date(try_get_image_date_with(path, policy))
+}
+
+/// Like `get_image_date`, but for an image held in memory. Only the dates embedded in the image
+/// are considered, since there is no file to have filesystem times.
+pub fn get_image_date_from_bytes(bytes: &[u8]) -> i64 {
+    or_err_date(try_get_image_date_from_bytes(bytes))
+}
+
+fn or_err_date(date: Result<ImageDate, ImageDateError>) -> i64 {
+    match date {
+        Ok(date) => date.timestamp,
         // literally nothing worked, so here is the fallback - a date in the future so this will be
         // noticed
-        ERR_DATE
+        Err(_) => ERR_DATE,
+    }
+}
+
+/// Determines the date the image was taken, reporting why if it could not be.
+///
+/// When no source yields a date, the most specific problem encountered along the way is returned:
+/// a malformed date field over an unreadable container, and either over `NoSource`.
+pub fn try_get_image_date<P: AsRef<Path>>(path: P) -> Result<ImageDate, ImageDateError> {
+    try_get_image_date_with(path, &DatePolicy::default())
+}
+
+/// Like `try_get_image_date`, but with the sources and their priority decided by `policy`.
+pub fn try_get_image_date_with<P: AsRef<Path>>(
+    path: P,
+    policy: &DatePolicy,
+) -> Result<ImageDate, ImageDateError> {
+    // the first step is to see if we can even open the file...
+    let path = path.as_ref();
+    let file = File::open(path)?;
+
+    // now create a vector to hold all of the dates we hope we can find
+    let mut candidates: Vec<DateCandidate> = Vec::new();
+    let container = get_image_dates(path, &file, policy, &mut candidates);
+    select_date(candidates, container, policy)
+}
+
+/// Like `try_get_image_date`, but for an image held in memory. Only the dates embedded in the
+/// image are considered, since there is no file to have filesystem times.
+pub fn try_get_image_date_from_bytes(bytes: &[u8]) -> Result<ImageDate, ImageDateError> {
+    try_get_image_date_from_reader(&mut Cursor::new(bytes))
+}
+
+/// Like `try_get_image_date`, but reading the image from `reader`, which must be positioned at its
+/// start. Only the dates embedded in the image are considered.
+pub fn try_get_image_date_from_reader<R: BufRead + Seek>(
+    reader: &mut R,
+) -> Result<ImageDate, ImageDateError> {
+    try_get_image_date_from_reader_with(reader, &DatePolicy::default())
+}
+
+/// Like `try_get_image_date_from_reader`, but with the sources and their priority decided by
+/// `policy`.
+pub fn try_get_image_date_from_reader_with<R: BufRead + Seek>(
+    reader: &mut R,
+    policy: &DatePolicy,
+) -> Result<ImageDate, ImageDateError> {
+    let mut candidates: Vec<DateCandidate> = Vec::new();
+    let container = get_embedded_dates(reader, policy, &mut candidates);
+    select_date(candidates, container, policy)
+}
+
+/// Returns every date the image carries, from every source, including the ones that could not be
+/// parsed. They are in the default order of priority, so the first that parsed is the one
+/// `try_get_image_date` would return.
+///
+/// A file Exif data cannot be read from simply has no Exif candidates. An Exif date in both the
+/// primary image's directory and the thumbnail's (IFD1) gives a candidate for each, the primary
+/// one first.
+pub fn all_image_dates<P: AsRef<Path>>(path: P) -> Result<Vec<DateCandidate>, ImageDateError> {
+    all_image_dates_with(path, &DatePolicy::default())
+}
+
+/// Like `all_image_dates`, but with the sources and their priority decided by `policy`, so the
+/// first candidate that parsed is the one `try_get_image_date_with` would return.
+pub fn all_image_dates_with<P: AsRef<Path>>(
+    path: P,
+    policy: &DatePolicy,
+) -> Result<Vec<DateCandidate>, ImageDateError> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+
+    let mut candidates: Vec<DateCandidate> = Vec::new();
+    let _ = get_image_dates(path, &file, policy, &mut candidates);
+    candidates.sort_by_key(|candidate| policy.rank(candidate.source));
+    Ok(candidates)
+}
+
+/// Returns the candidate `policy` ranks highest, or failing that the first thing that went wrong.
+fn select_date(
+    candidates: Vec<DateCandidate>,
+    container: Result<(), ImageDateError>,
+    policy: &DatePolicy,
+) -> Result<ImageDate, ImageDateError> {
+    let mut dates: Vec<ImageDate> = Vec::new();
+    let mut malformed = None;
+    for candidate in candidates {
+        match candidate.date {
+            Ok(date) => dates.push(date),
+            Err(err) => malformed = malformed.or(Some(err)),
+        }
+    }
+    match dates
+        .into_iter()
+        .min_by_key(|date| policy.rank(date.source))
+    {
+        Some(date) => Ok(date),
+        None => Err(malformed
+            .or_else(|| container.err())
+            .unwrap_or(ImageDateError::NoSource)),
+    }
+}
+
+/// Collects the candidates from every source `policy` enables. Returns an error only when the
+/// file's Exif data could not be read at all.
+fn get_image_dates(
+    path: &Path,
+    file: &File,
+    policy: &DatePolicy,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let container = get_embedded_dates(&mut BufReader::new(file), policy, candidates);
+    if policy.is_enabled(DateSource::XmpSidecar) {
+        get_sidecar_dates(path, candidates);
+    }
+    if policy.is_enabled(DateSource::FileName) {
+        get_filename_dates(path, policy, candidates);
+    }
+    if policy.is_enabled(DateSource::DirectoryName) {
+        get_directory_dates(path, candidates);
     }
+    get_filesystem_dates(file, policy, candidates);
+    container
 }
 
-fn get_exif_image_dates(file: &File, dates: &mut Vec<(u8, u64)>) {
-    let exif = match Reader::new().read_from_container(&mut BufReader::new(file)) {
-        Ok(exif) => exif,
-        Err(_) => return, // Could not create the exif reader, so there is nothing more to do here
+/// Collects the candidates from the metadata embedded in the image itself.
+fn get_embedded_dates<R: BufRead + Seek>(
+    reader: &mut R,
+    policy: &DatePolicy,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let start = reader.stream_position()?;
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    reader.take(HEAD_LEN as u64).read_to_end(&mut head)?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    let xmp = policy.is_enabled(DateSource::XmpEmbedded);
+    let mut container = Ok(());
+    let mut exif = None;
+    // TIFF keeps its XMP packet among its tags, so XMP needs the Exif attributes too. X3F has no
+    // Exif attributes to miss, as its dates are among its own properties instead.
+    if (policy.any_enabled(&EXIF_SOURCES) || xmp) && !x3f::is_x3f(&head) {
+        match read_exif(reader, &head) {
+            Ok(read) => exif = Some(read),
+            Err(err) => container = Err(ImageDateError::UnreadableContainer(err)),
+        }
+    }
+    if let Some(exif) = &exif {
+        get_exif_image_dates(exif, policy, candidates);
+    }
+    if xmp {
+        reader.seek(SeekFrom::Start(start))?;
+        match read_xmp(reader, &head, exif.as_ref()) {
+            Ok(Some(packet)) => get_xmp_dates(&packet, DateSource::XmpEmbedded, candidates),
+            Ok(None) => (),
+            Err(err) => keep_first_error(&mut container, Err(err.into())),
+        }
+    }
+    if jpeg::is_jpeg(&head) && policy.is_enabled(DateSource::IptcDateCreated) {
+        reader.seek(SeekFrom::Start(start))?;
+        keep_first_error(&mut container, get_iptc_dates(reader, candidates));
+    }
+    if !heif::is_heif(&head)
+        && quicktime::is_quicktime(&head)
+        && policy.any_enabled(&QUICKTIME_SOURCES)
+    {
+        reader.seek(SeekFrom::Start(start))?;
+        keep_first_error(
+            &mut container,
+            get_quicktime_dates(reader, policy, candidates),
+        );
+    }
+    if png::is_png(&head) && policy.is_enabled(DateSource::PngCreationTime) {
+        reader.seek(SeekFrom::Start(start))?;
+        keep_first_error(&mut container, get_png_dates(reader, candidates));
+    }
+    if x3f::is_x3f(&head) && policy.is_enabled(DateSource::X3fTime) {
+        reader.seek(SeekFrom::Start(start))?;
+        keep_first_error(&mut container, get_x3f_dates(reader, candidates));
+    }
+    container
+}
+
+/// Records what went wrong reading one part of the image, unless something already had, so the
+/// parts after it are still read.
+fn keep_first_error(
+    container: &mut Result<(), ImageDateError>,
+    result: Result<(), ImageDateError>,
+) {
+    if container.is_ok() {
+        *container = result;
+    }
+}
+
+/// Records the outcome of reading one tag, unless the tag was absent.
+fn push_candidate(
+    candidates: &mut Vec<DateCandidate>,
+    source: DateSource,
+    tag: &str,
+    date: Result<Option<ImageDate>, ImageDateError>,
+) {
+    let date = match date {
+        Ok(Some(date)) => Ok(date),
+        Ok(None) => return,
+        Err(err) => Err(err),
     };
+    candidates.push(DateCandidate {
+        source,
+        tag: tag.to_string(),
+        date,
+    });
+}
+
+// Reads the Exif attributes from any of the containers we know, handing those kamadak-exif reads
+// itself over to it. `head` is the start of the file, which tells its format.
+fn read_exif<R: BufRead + Seek>(reader: &mut R, head: &[u8]) -> Result<exif::Exif, exif::Error> {
+    if heif::is_heif(head) {
+        return Reader::new().read_raw(heif::get_exif_attr(reader)?);
+    }
+    if cr3::is_cr3(head) {
+        return Reader::new().read_raw(cr3::get_exif_attr(reader)?);
+    }
+    if png::is_png(head) {
+        return Reader::new().read_raw(png::get_exif_attr(reader)?);
+    }
+    if webp::is_webp(head) {
+        return Reader::new().read_raw(webp::get_exif_attr(reader)?);
+    }
+    if jxl::is_jxl(head) {
+        return Reader::new().read_raw(jxl::get_exif_attr(reader)?);
+    }
+    if raf::is_raf(head) {
+        return Reader::new().read_from_container(&mut Cursor::new(raf::get_jpeg(reader)?));
+    }
+    if tiff::is_tiff(head) {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        return tiff::read_exif(data);
+    }
+    Reader::new().read_from_container(reader)
+}
+
+fn get_exif_image_dates(
+    exif: &exif::Exif,
+    policy: &DatePolicy,
+    candidates: &mut Vec<DateCandidate>,
+) {
+    // 0x001d GPSDateStamp (UTC date of the GPS fix)
+    // 0x0007 GPSTimeStamp (UTC time of the GPS fix)
+    let gps = get_exif_gps_date(exif);
 
     // 0x9003 DateTimeOriginal   (date/time when original image was taken)
+    // 0x9291 SubSecTimeOriginal (fractional seconds for DateTimeOriginal)
     // 0x9011 OffsetTimeOriginal (time zone for DateTimeOriginal)
-    if let Ok(t) = get_exif_date(
-        &exif,
-        exif::Tag::DateTimeOriginal,
-        exif::Tag::OffsetTimeOriginal,
-    ) {
-        // we are going in order of priority, so if this worked there is no need to proceed any
-        // further
-        dates.push((EXIF_DATE_TIME_ORIGINAL, t));
-        return;
+    if policy.is_enabled(DateSource::ExifDateTimeOriginal) {
+        let dates = get_exif_date(
+            exif,
+            DateSource::ExifDateTimeOriginal,
+            exif::Tag::DateTimeOriginal,
+            exif::Tag::SubSecTimeOriginal,
+            exif::Tag::OffsetTimeOriginal,
+        );
+        for (ifd_num, mut original) in dates {
+            if let Ok(Some(date)) = &mut original {
+                // a camera rarely records its time zone, but if it also recorded a GPS fix the
+                // difference between the two tells us what it was
+                if date.offset.is_none() {
+                    if let Ok(Some(gps)) = &gps {
+                        if let Some(offset) = infer_offset(date, gps) {
+                            date.set_inferred_offset(offset);
+                        }
+                    }
+                }
+                set_location_offset(exif, date);
+            }
+            push_candidate(
+                candidates,
+                DateSource::ExifDateTimeOriginal,
+                &get_exif_tag_name("DateTimeOriginal", ifd_num),
+                original,
+            );
+        }
+    }
+
+    if policy.is_enabled(DateSource::ExifGps) {
+        push_candidate(
+            candidates,
+            DateSource::ExifGps,
+            "GPSDateStamp, GPSTimeStamp",
+            gps,
+        );
     }
 
     // 0x9004 CreateDate          (called DateTimeDigitized by the EXIF spec.)
+    // 0x9292 SubSecTimeDigitized (fractional seconds for CreateDate)
     // 0x9012 OffsetTimeDigitized (time zone for CreateDate)
-    if let Ok(t) = get_exif_date(&exif, exif::Tag::DateTime, exif::Tag::OffsetTime) {
-        dates.push((EXIF_CREATE_DATE, t));
-        return;
+    if policy.is_enabled(DateSource::ExifCreateDate) {
+        let dates = get_exif_date(
+            exif,
+            DateSource::ExifCreateDate,
+            exif::Tag::DateTimeDigitized,
+            exif::Tag::SubSecTimeDigitized,
+            exif::Tag::OffsetTimeDigitized,
+        );
+        for (ifd_num, mut digitized) in dates {
+            if let Ok(Some(date)) = &mut digitized {
+                set_location_offset(exif, date);
+            }
+            push_candidate(
+                candidates,
+                DateSource::ExifCreateDate,
+                &get_exif_tag_name("DateTimeDigitized", ifd_num),
+                digitized,
+            );
+        }
     }
 
     // 0x0132 ModifyDate (called DateTime by the EXIF spec.)
+    // 0x9290 SubSecTime (fractional seconds for ModifyDate)
     // 0x9010 OffsetTime (time zone for ModifyDate)
-    if let Ok(t) = get_exif_date(&exif, exif::Tag::DateTime, exif::Tag::OffsetTime) {
-        dates.push((EXIF_MODIFY_DATE, t));
+    if policy.is_enabled(DateSource::ExifModifyDate) {
+        let dates = get_exif_date(
+            exif,
+            DateSource::ExifModifyDate,
+            exif::Tag::DateTime,
+            exif::Tag::SubSecTime,
+            exif::Tag::OffsetTime,
+        );
+        for (ifd_num, mut modify) in dates {
+            if let Ok(Some(date)) = &mut modify {
+                set_location_offset(exif, date);
+            }
+            push_candidate(
+                candidates,
+                DateSource::ExifModifyDate,
+                &get_exif_tag_name("DateTime", ifd_num),
+                modify,
+            );
+        }
+    }
+}
+
+/// Reads a date from the primary image's directory and from the thumbnail's, which some phones and
+/// scanners write it to instead, in that order. Each is `Ok(None)` when the field is absent there,
+/// and an error when it is present but unparsable.
+fn get_exif_date(
+    exif: &exif::Exif,
+    source: DateSource,
+    date: exif::Tag,
+    subsec: exif::Tag,
+    timezone: exif::Tag,
+) -> [(In, Result<Option<ImageDate>, ImageDateError>); 2] {
+    [In::PRIMARY, In::THUMBNAIL].map(|ifd_num| {
+        (
+            ifd_num,
+            get_exif_ifd_date(exif, ifd_num, source, date, subsec, timezone),
+        )
+    })
+}
+
+// Names a candidate from the thumbnail's directory apart from the primary image's
+fn get_exif_tag_name(tag: &str, ifd_num: In) -> String {
+    if ifd_num == In::THUMBNAIL {
+        format!("{} (IFD1)", tag)
+    } else {
+        tag.to_string()
     }
 }
 
-fn get_exif_date(exif: &exif::Exif, date: exif::Tag, _timezone: exif::Tag) -> Result<u64, String> {
-    // TODO: Check for In::THUMBNAIL as well
-    let date_field = match exif.get_field(date, In::PRIMARY) {
+fn get_exif_ifd_date(
+    exif: &exif::Exif,
+    ifd_num: In,
+    source: DateSource,
+    date: exif::Tag,
+    subsec: exif::Tag,
+    timezone: exif::Tag,
+) -> Result<Option<ImageDate>, ImageDateError> {
+    let date_field = match exif.get_field(date, ifd_num) {
         Some(date) => date,
-        _ => return Err("Failed to extract exif field".to_string()),
+        _ => return Ok(None),
+    };
+
+    let date_string = match get_exif_ascii(&date_field.value) {
+        Some(date_string) => date_string,
+        None => {
+            let value = format!("{}", date_field.value.display_as(date));
+            return Err(ImageDateError::MalformedDate(value));
+        }
+    };
+    let (no_timezone, repaired) = match parse::parse_exif_date(&date_string) {
+        Lenient::Exact(time) => (time, false),
+        Lenient::Repaired(time) => (time, true),
+        // a placeholder is as good as no field at all
+        Lenient::Placeholder => return Ok(None),
+        Lenient::Malformed => return Err(ImageDateError::MalformedDate(date_string)),
+    };
+    let subsec_nanos = get_exif_subsec(exif, subsec, ifd_num);
+    let no_timezone = match subsec_nanos {
+        // this can only fail for a leap second, in which case the whole second is good enough
+        Some(nanos) => no_timezone.with_nanosecond(nanos).unwrap_or(no_timezone),
+        None => no_timezone,
+    };
+
+    // Exif times are local wall-clock times. Only once the matching offset tag (Exif 2.31) tells us
+    // which zone that was can they be turned into a real instant; otherwise we treat them as UTC,
+    // which is what every other time in this library is compared against.
+    let offset = get_exif_offset(exif, timezone, ifd_num);
+    let date_time = match offset {
+        Some(offset) => match offset.from_local_datetime(&no_timezone).single() {
+            Some(date_time) => date_time,
+            None => return Err(ImageDateError::MalformedDate(date_string)),
+        },
+        None => no_timezone.and_utc().fixed_offset(),
+    };
+    let mut image_date = ImageDate::new(source, date_time, offset);
+    image_date.subsec_nanos = subsec_nanos;
+    image_date.raw = Some(date_string);
+    image_date.thumbnail = ifd_num == In::THUMBNAIL;
+    image_date.repaired = repaired;
+    Ok(Some(image_date))
+}
+
+/// Returns `Ok(None)` when either GPS field is absent, and an error when they are present but
+/// unparsable.
+fn get_exif_gps_date(exif: &exif::Exif) -> Result<Option<ImageDate>, ImageDateError> {
+    let (date_field, time_field) = match (
+        exif.get_field(exif::Tag::GPSDateStamp, In::PRIMARY),
+        exif.get_field(exif::Tag::GPSTimeStamp, In::PRIMARY),
+    ) {
+        (Some(date), Some(time)) => (date, time),
+        _ => return Ok(None),
+    };
+
+    let raw = format!(
+        "{} {}",
+        get_exif_ascii(&date_field.value).unwrap_or_default(),
+        time_field.display_value()
+    );
+    let date = get_exif_ascii(&date_field.value)
+        .and_then(|date| NaiveDate::parse_from_str(date.trim(), "%Y:%m:%d").ok());
+    let time = match &time_field.value {
+        exif::Value::Rational(hms) if hms.len() == 3 => {
+            // the seconds may be fractional, so work in nanoseconds from here on
+            let nanos = hms[0].to_f64() * 3_600e9 + hms[1].to_f64() * 60e9 + hms[2].to_f64() * 1e9;
+            if (0.0..86_400e9).contains(&nanos) {
+                let nanos = nanos.round() as u64;
+                NaiveTime::from_num_seconds_from_midnight_opt(
+                    (nanos / 1_000_000_000) as u32,
+                    (nanos % 1_000_000_000) as u32,
+                )
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    let (date, time) = match (date, time) {
+        (Some(date), Some(time)) => (date, time),
+        _ => return Err(ImageDateError::MalformedDate(raw)),
+    };
+
+    // GPS times are always UTC, so unlike the other Exif times the offset is known
+    let mut image_date = ImageDate::new(
+        DateSource::ExifGps,
+        date.and_time(time).and_utc().fixed_offset(),
+        Some(Utc.fix()),
+    );
+    if time.nanosecond() != 0 {
+        image_date.subsec_nanos = Some(time.nanosecond());
+    }
+    image_date.raw = Some(raw);
+    Ok(Some(image_date))
+}
+
+/// Infers the offset of a naive Exif date from the time zone at the image's GPS coordinates.
+#[cfg(feature = "tz-lookup")]
+fn set_location_offset(exif: &exif::Exif, date: &mut ImageDate) {
+    if date.offset.is_none() {
+        if let Some(offset) = tz::get_exif_location_offset(exif, date.date_time.naive_local()) {
+            date.set_inferred_offset(offset);
+        }
+    }
+}
+
+#[cfg(not(feature = "tz-lookup"))]
+fn set_location_offset(_exif: &exif::Exif, _date: &mut ImageDate) {}
+
+/// Infers the offset of a naive camera time from a GPS time taken at the same moment. The
+/// difference is rounded to the nearest 15 minutes, the granularity of real-world time zones,
+/// which also absorbs a fix that is a few minutes stale. Returns `None` when the difference could
+/// not be a time zone, e.g. because the camera clock was never set.
+fn infer_offset(local: &ImageDate, gps: &ImageDate) -> Option<FixedOffset> {
+    let difference = local.date_time.naive_local() - gps.date_time.naive_utc();
+    let quarter_hours = (difference.num_seconds() as f64 / 900.0).round() as i32;
+    if quarter_hours.abs() > 14 * 4 {
+        return None;
+    }
+    FixedOffset::east_opt(quarter_hours * 900)
+}
+
+/// Returns the fractional seconds, in nanoseconds, of an Exif SubSecTime* tag.
+fn get_exif_subsec(exif: &exif::Exif, subsec: exif::Tag, ifd_num: In) -> Option<u32> {
+    let field = exif.get_field(subsec, ifd_num)?;
+    parse_subsec(&get_exif_ascii(&field.value)?)
+}
+
+/// Parses Exif fractional seconds, where "5" means half a second and "123" 123 milliseconds.
+/// Trailing blanks are allowed by the spec.; digits past nanosecond precision are dropped.
+fn parse_subsec(subsec: &str) -> Option<u32> {
+    let digits = subsec.trim_end_matches([' ', '\0']);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let nanos = digits
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |nanos, digit| nanos * 10 + u32::from(digit - b'0'));
+    Some(nanos)
+}
+
+fn get_exif_offset(exif: &exif::Exif, timezone: exif::Tag, ifd_num: In) -> Option<FixedOffset> {
+    let field = exif.get_field(timezone, ifd_num)?;
+    parse_offset(&get_exif_ascii(&field.value)?)
+}
+
+/// Parses an Exif offset such as "+09:00". Blank offsets ("   :  ") mean unknown and yield `None`.
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let offset = offset.trim();
+    if offset.len() != 6 || !offset.is_ascii() || &offset[3..4] != ":" {
+        return None;
+    }
+
+    let sign = match &offset[0..1] {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours: i32 = offset[1..3].parse().ok()?;
+    let minutes: i32 = offset[4..6].parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Returns the first string of an ASCII field exactly as it was stored.
+fn get_exif_ascii(value: &exif::Value) -> Option<String> {
+    match value {
+        exif::Value::Ascii(strings) => strings
+            .first()
+            .map(|string| String::from_utf8_lossy(string).into_owned()),
+        _ => None,
+    }
+}
+
+fn get_png_dates<R: BufRead + Seek>(
+    reader: &mut R,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let mut texts = Vec::new();
+    let read = png::get_text(reader, "Creation Time", &mut texts);
+    for (kind, text) in texts {
+        let tag = format!("{} Creation Time", String::from_utf8_lossy(&kind));
+        let date = get_text_date(DateSource::PngCreationTime, text);
+        push_candidate(candidates, DateSource::PngCreationTime, &tag, date);
+    }
+    Ok(read?)
+}
+
+fn get_x3f_dates<R: BufRead + Seek>(
+    reader: &mut R,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let text = match x3f::get_property(reader, "TIME")? {
+        Some(text) => text,
+        None => return Ok(()),
+    };
+    let date = text
+        .trim()
+        .parse()
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        .map(|date_time| {
+            let mut date = ImageDate::new(DateSource::X3fTime, date_time.fixed_offset(), None);
+            date.raw = Some(text.clone());
+            date
+        })
+        .ok_or_else(|| ImageDateError::MalformedDate(text.clone()));
+    push_candidate(candidates, DateSource::X3fTime, "TIME", date.map(Some));
+    Ok(())
+}
+
+// Reads the XMP packet from wherever the file's format keeps it
+fn read_xmp<R: BufRead + Seek>(
+    reader: &mut R,
+    head: &[u8],
+    exif: Option<&exif::Exif>,
+) -> io::Result<Option<String>> {
+    if jpeg::is_jpeg(head) {
+        return jpeg::get_xmp(reader);
+    }
+    if png::is_png(head) {
+        let mut texts = Vec::new();
+        let read = png::get_text(reader, "XML:com.adobe.xmp", &mut texts);
+        return match texts.into_iter().next() {
+            Some((_, text)) => Ok(Some(text)),
+            None => read.map(|()| None),
+        };
+    }
+    if webp::is_webp(head) {
+        let chunk = webp::read_chunk(reader, b"XMP ")?;
+        return Ok(chunk.map(|chunk| String::from_utf8_lossy(&chunk).into_owned()));
+    }
+
+    // 0x02bc XMLPacket (XMP, in TIFF and the raw formats built on it)
+    let field =
+        exif.and_then(|exif| exif.get_field(exif::Tag(exif::Context::Tiff, 0x02bc), In::PRIMARY));
+    Ok(field.and_then(|field| match &field.value {
+        exif::Value::Byte(bytes) | exif::Value::Undefined(bytes, _) => {
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        }
+        value => get_exif_ascii(value),
+    }))
+}
+
+fn get_sidecar_dates(path: &Path, candidates: &mut Vec<DateCandidate>) {
+    if let Some((_, sidecar)) = xmp::read_sidecar(path) {
+        get_xmp_dates(&sidecar, DateSource::XmpSidecar, candidates);
+    }
+}
+
+// Only the first pattern the name matches counts
+fn get_filename_dates(path: &Path, policy: &DatePolicy, candidates: &mut Vec<DateCandidate>) {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return,
+    };
+    let last_year = pattern::last_year();
+    for pattern in policy.filename_patterns() {
+        if let Some(parsed) = pattern::match_pattern(pattern, &name, last_year) {
+            let date = get_parsed_date(DateSource::FileName, Some(parsed), name.into_owned());
+            push_candidate(candidates, DateSource::FileName, pattern, date);
+            return;
+        }
+    }
+}
+
+fn get_directory_dates(path: &Path, candidates: &mut Vec<DateCandidate>) {
+    if let Some((pattern, directories, parsed)) =
+        pattern::match_directories(path, pattern::last_year())
+    {
+        let date = get_parsed_date(DateSource::DirectoryName, Some(parsed), directories);
+        push_candidate(candidates, DateSource::DirectoryName, pattern, date);
+    }
+}
+
+fn get_xmp_dates(xmp: &str, source: DateSource, candidates: &mut Vec<DateCandidate>) {
+    for property in xmp::DATE_PROPERTIES.iter() {
+        if let Some(value) = xmp::get_property(xmp, property) {
+            push_candidate(candidates, source, property, get_text_date(source, value));
+        }
+    }
+}
+
+fn get_quicktime_dates<R: BufRead + Seek>(
+    reader: &mut R,
+    policy: &DatePolicy,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let moov = match quicktime::read_movie(reader)? {
+        Some(moov) => moov,
+        None => return Ok(()),
+    };
+
+    if policy.is_enabled(DateSource::QuickTimeCreationDate) {
+        let key = "com.apple.quicktime.creationdate";
+        if let Some(text) = quicktime::get_metadata(&moov, key) {
+            let date = get_text_date(DateSource::QuickTimeCreationDate, text);
+            push_candidate(candidates, DateSource::QuickTimeCreationDate, key, date);
+        }
+    }
+
+    if policy.is_enabled(DateSource::QuickTimeDay) {
+        if let Some(text) = quicktime::get_day(&moov) {
+            let date = get_text_date(DateSource::QuickTimeDay, text);
+            push_candidate(candidates, DateSource::QuickTimeDay, "\u{a9}day", date);
+        }
+    }
+
+    // the headers count seconds since 1904 in UTC, though some cameras write their local time
+    if policy.is_enabled(DateSource::QuickTimeCreateDate) {
+        if let Some((header, secs)) = quicktime::get_creation_time(&moov) {
+            let date = Utc
+                .timestamp_opt(secs, 0)
+                .single()
+                .map(|date_time| {
+                    let source = DateSource::QuickTimeCreateDate;
+                    ImageDate::new(source, date_time.fixed_offset(), Some(Utc.fix()))
+                })
+                .ok_or_else(|| ImageDateError::MalformedDate(secs.to_string()));
+            push_candidate(
+                candidates,
+                DateSource::QuickTimeCreateDate,
+                header,
+                date.map(Some),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn get_iptc_dates<R: BufRead + Seek>(
+    reader: &mut R,
+    candidates: &mut Vec<DateCandidate>,
+) -> Result<(), ImageDateError> {
+    let iim = match iptc::get_iim(reader)? {
+        Some(iim) => iim,
+        None => return Ok(()),
+    };
+    let date = match iptc::get_dataset(&iim, iptc::DATE_CREATED) {
+        Some(date) => String::from_utf8_lossy(date).into_owned(),
+        None => return Ok(()),
     };
+    // the time means nothing without the date, but the date is fine without the time
+    let time = iptc::get_dataset(&iim, iptc::TIME_CREATED)
+        .map(|time| String::from_utf8_lossy(time).into_owned());
 
-    let date_string = format!("{}", date_field.value.display_as(date));
-    let no_timezone = match NaiveDateTime::parse_from_str(&date_string, "%Y-%m-%d %H:%M:%S") {
-        Ok(time) => time,
-        _ => return Err("Failed to match date format extracted from exif data".to_string()),
+    let parsed = parse::parse_iim_date(&date, time.as_deref());
+    let (tag, raw) = match &time {
+        Some(time) => ("DateCreated, TimeCreated", format!("{} {}", date, time)),
+        None => ("DateCreated", date),
     };
+    let date = get_parsed_date(DateSource::IptcDateCreated, parsed, raw);
+    push_candidate(candidates, DateSource::IptcDateCreated, tag, date);
+    Ok(())
+}
+
+/// Reads a date stored as free-form text, which unlike an Exif date may carry its own offset, and
+/// may be only partly recorded.
+fn get_text_date(source: DateSource, text: String) -> Result<Option<ImageDate>, ImageDateError> {
+    let parsed = parse::parse_text_date(&text);
+    get_parsed_date(source, parsed, text)
+}
 
-    // We will force this to UTC time since we do not use the exact time and then
-    // we can have matching types.
-    // TODO: How to use supplied timezone information?
-    Ok(no_timezone.timestamp() as u64)
+/// Turns a date parsed from `raw` into an `ImageDate`, or reports `raw` if it could not be parsed.
+fn get_parsed_date(
+    source: DateSource,
+    parsed: Option<TextDate>,
+    raw: String,
+) -> Result<Option<ImageDate>, ImageDateError> {
+    if parse::parse_exif_date(&raw) == Lenient::Placeholder {
+        return Ok(None);
+    }
+    let (no_timezone, offset, precision) = match parsed {
+        Some(date) => (date.local, date.offset, date.precision),
+        None => return Err(ImageDateError::MalformedDate(raw)),
+    };
+    let date_time = match offset {
+        Some(offset) => match offset.from_local_datetime(&no_timezone).single() {
+            Some(date_time) => date_time,
+            None => return Err(ImageDateError::MalformedDate(raw)),
+        },
+        None => no_timezone.and_utc().fixed_offset(),
+    };
+    let mut image_date = ImageDate::new(source, date_time, offset);
+    image_date.subsec_nanos = match no_timezone.nanosecond() {
+        0 => None,
+        nanos => Some(nanos),
+    };
+    image_date.raw = Some(raw);
+    image_date.precision = precision;
+    Ok(Some(image_date))
 }
 
-fn get_filesystem_dates(file: &File, dates: &mut Vec<(u8, u64)>) {
+fn get_filesystem_dates(file: &File, policy: &DatePolicy, candidates: &mut Vec<DateCandidate>) {
     let metadata = match file.metadata() {
         Ok(metadata) => metadata,
         Err(_) => return, // This platform does not support metadata, so there is nothing more to do here
@@ -119,26 +1100,22 @@ fn get_filesystem_dates(file: &File, dates: &mut Vec<(u8, u64)>) {
     // The returned value corresponds to the btime field of statx on Linux kernel starting from to
     // 4.11, the birthtime field of stat on other Unix platforms, and the ftCreationTime field on
     // Windows platforms.
-    if let Ok(t) = metadata.created() {
-        let since_epoch = t
-            .duration_since(UNIX_EPOCH)
-            .expect("Time is running backwards");
-        let since_epoch = since_epoch.as_secs(); // we can ignore the nano second portion
-        dates.push((SYS_CREATED, since_epoch));
-        // we are going in order of priority, so if this worked there is no need to proceed any
-        // further
-        return;
+    if policy.is_enabled(DateSource::SysCreated) {
+        let date = metadata
+            .created()
+            .ok()
+            .and_then(|t| ImageDate::from_system_time(DateSource::SysCreated, t));
+        push_candidate(candidates, DateSource::SysCreated, "created", Ok(date));
     }
 
     // The returned value corresponds to the mtime field of stat on Unix platforms and the
     // ftLastWriteTime field on Windows platforms.
-    if let Ok(t) = metadata.modified() {
-        let since_epoch = t
-            .duration_since(UNIX_EPOCH)
-            .expect("Time is running backwards");
-        let since_epoch = since_epoch.as_secs(); // we can ignore the nano second portion
-        dates.push((SYS_MODIFIED, since_epoch));
-        return;
+    if policy.is_enabled(DateSource::SysModified) {
+        let date = metadata
+            .modified()
+            .ok()
+            .and_then(|t| ImageDate::from_system_time(DateSource::SysModified, t));
+        push_candidate(candidates, DateSource::SysModified, "modified", Ok(date));
     }
 
     // The returned value corresponds to the atime field of stat on Unix platforms and the
@@ -147,12 +1124,12 @@ fn get_filesystem_dates(file: &File, dates: &mut Vec<(u8, u64)>) {
     // Note that not all platforms will keep this field update in a file's metadata, for example
     // Windows has an option to disable updating this time when files are accessed and Linux
     // similarly has noatime.
-    if let Ok(t) = metadata.accessed() {
-        let since_epoch = t
-            .duration_since(UNIX_EPOCH)
-            .expect("Time is running backwards");
-        let since_epoch = since_epoch.as_secs(); // we can ignore the nano second portion
-        dates.push((SYS_ACCESSED, since_epoch));
+    if policy.is_enabled(DateSource::SysAccessed) {
+        let date = metadata
+            .accessed()
+            .ok()
+            .and_then(|t| ImageDate::from_system_time(DateSource::SysAccessed, t));
+        push_candidate(candidates, DateSource::SysAccessed, "accessed", Ok(date));
     }
 }
 
@@ -162,11 +1139,68 @@ fn get_filesystem_dates(file: &File, dates: &mut Vec<(u8, u64)>) {
 mod tests {
     use super::*;
 
+    use std::fs;
     use std::path::Path;
+    use std::path::PathBuf;
     use std::time::Instant;
 
+    use exif::experimental::Writer;
+    use exif::Field;
+    use exif::Rational;
+    use exif::Tag;
+    use exif::Value;
+
     use walkdir::WalkDir;
 
+    pub(crate) fn ascii(tag: Tag, ifd_num: In, value: &str) -> Field {
+        Field {
+            tag,
+            ifd_num,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    fn gps_time(hours: u32, minutes: u32, seconds: u32, denom: u32) -> Field {
+        let hms = vec![
+            Rational::from((hours, 1)),
+            Rational::from((minutes, 1)),
+            Rational::from((seconds, denom)),
+        ];
+        Field {
+            tag: Tag::GPSTimeStamp,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(hms),
+        }
+    }
+
+    // Builds a little endian TIFF holding only the given fields
+    pub(crate) fn tiff(fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        buf.into_inner()
+    }
+
+    // Writes a TIFF holding only the given fields to the temporary directory
+    fn write_tiff(name: &str, fields: &[Field]) -> PathBuf {
+        write_temp(&format!("{}.tif", name), &tiff(fields))
+    }
+
+    // The default policy, but for the directory names, as those of the temporary directory are
+    // not the tests' to choose
+    fn without_directories() -> DatePolicy {
+        DatePolicy::new().disable(DateSource::DirectoryName)
+    }
+
+    pub(crate) fn write_temp(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("imagedt-{}", name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
     #[test]
     fn date_time_original() {
         // image: Canon_40D.jpg
@@ -185,6 +1219,842 @@ mod tests {
         assert_eq!(time, 1606381089);
     }
 
+    #[test]
+    fn missing_file() {
+        let filename = "this-file-does-not-exist.jpg";
+        match try_get_image_date(filename) {
+            Err(ImageDateError::Io(_)) => (),
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+        assert_eq!(get_image_date(filename), ERR_DATE);
+    }
+
+    #[test]
+    fn exif_source() {
+        let path = write_tiff(
+            "exif-source",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "2008:05:30 15:56:01",
+            )],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+        assert_eq!(date.raw.as_deref(), Some("2008:05:30 15:56:01"));
+    }
+
+    #[test]
+    fn exif_offset() {
+        let path = write_tiff(
+            "exif-offset",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2008:05:30 15:56:01"),
+                ascii(Tag::OffsetTimeOriginal, In::PRIMARY, "+09:00"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        let offset = FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(date.offset, Some(offset));
+        assert_eq!(date.timestamp, 1212162961 - 9 * 3600);
+        assert_eq!(date.date_time.offset(), &offset);
+        assert_eq!(date.date_time.to_string(), "2008-05-30 15:56:01 +09:00");
+    }
+
+    #[test]
+    fn exif_blank_offset() {
+        let path = write_tiff(
+            "exif-blank-offset",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2008:05:30 15:56:01"),
+                ascii(Tag::OffsetTimeOriginal, In::PRIMARY, "   :  "),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.offset, None);
+        assert_eq!(date.timestamp, 1212162961);
+    }
+
+    #[test]
+    fn exif_tag_priority() {
+        let original = ascii(Tag::DateTimeOriginal, In::PRIMARY, "2001:01:01 01:01:01");
+        let digitized = ascii(Tag::DateTimeDigitized, In::PRIMARY, "2002:02:02 02:02:02");
+        let digitized_offset = ascii(Tag::OffsetTimeDigitized, In::PRIMARY, "-05:00");
+        let modify = ascii(Tag::DateTime, In::PRIMARY, "2003:03:03 03:03:03");
+        let modify_offset = ascii(Tag::OffsetTime, In::PRIMARY, "+01:00");
+
+        let path = write_tiff(
+            "exif-all-three",
+            &[
+                original.clone(),
+                digitized.clone(),
+                digitized_offset.clone(),
+                modify.clone(),
+                modify_offset.clone(),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.raw.as_deref(), Some("2001:01:01 01:01:01"));
+
+        let path = write_tiff(
+            "exif-digitized",
+            &[
+                digitized,
+                digitized_offset,
+                modify.clone(),
+                modify_offset.clone(),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifCreateDate);
+        assert_eq!(date.raw.as_deref(), Some("2002:02:02 02:02:02"));
+        assert_eq!(date.offset, FixedOffset::west_opt(5 * 3600));
+
+        let path = write_tiff("exif-modify", &[modify, modify_offset]);
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+        assert_eq!(date.raw.as_deref(), Some("2003:03:03 03:03:03"));
+        assert_eq!(date.offset, FixedOffset::east_opt(3600));
+    }
+
+    #[test]
+    fn exif_subsec() {
+        let mut dates = Vec::new();
+        for (name, subsec) in &[("exif-subsec-1", "12"), ("exif-subsec-2", "5  ")] {
+            let path = write_tiff(
+                name,
+                &[
+                    ascii(Tag::DateTimeOriginal, In::PRIMARY, "2008:05:30 15:56:01"),
+                    ascii(Tag::SubSecTimeOriginal, In::PRIMARY, subsec),
+                ],
+            );
+            dates.push(try_get_image_date(&path).unwrap());
+        }
+        assert_eq!(dates[0].timestamp, dates[1].timestamp);
+        assert_eq!(dates[0].subsec_nanos, Some(120_000_000));
+        assert_eq!(dates[1].subsec_nanos, Some(500_000_000));
+        assert!(dates[0].date_time < dates[1].date_time);
+    }
+
+    #[test]
+    fn subsec_parsing() {
+        assert_eq!(parse_subsec("0"), Some(0));
+        assert_eq!(parse_subsec("123"), Some(123_000_000));
+        assert_eq!(parse_subsec("1234567891"), Some(123_456_789));
+        assert_eq!(parse_subsec("   "), None);
+        assert_eq!(parse_subsec("1a"), None);
+    }
+
+    #[test]
+    fn exif_thumbnail() {
+        let path = write_tiff(
+            "exif-thumbnail",
+            &[
+                ascii(Tag::ImageDescription, In::PRIMARY, "no dates in here"),
+                ascii(Tag::DateTime, In::THUMBNAIL, "2008:05:30 15:56:01"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+        assert_eq!(date.timestamp, 1212162961);
+        assert!(date.thumbnail);
+
+        let path = write_tiff(
+            "exif-primary-and-thumbnail",
+            &[
+                ascii(Tag::DateTime, In::PRIMARY, "2008:05:30 15:56:01"),
+                ascii(Tag::DateTime, In::THUMBNAIL, "2009:01:01 00:00:00"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.timestamp, 1212162961);
+        assert!(!date.thumbnail);
+
+        // both are reported
+        let candidates = all_image_dates(&path).unwrap();
+        let modify: Vec<(&str, bool)> = candidates
+            .iter()
+            .filter(|candidate| candidate.source == DateSource::ExifModifyDate)
+            .map(|candidate| {
+                let date = candidate.date.as_ref().unwrap();
+                (candidate.tag.as_str(), date.thumbnail)
+            })
+            .collect();
+        assert_eq!(modify, vec![("DateTime", false), ("DateTime (IFD1)", true)]);
+
+        let path = write_tiff(
+            "exif-malformed-primary",
+            &[
+                ascii(Tag::DateTime, In::PRIMARY, "yesterday"),
+                ascii(Tag::DateTime, In::THUMBNAIL, "2008:05:30 15:56:01"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.timestamp, 1212162961);
+        assert!(date.thumbnail);
+    }
+
+    #[test]
+    fn exif_gps() {
+        let path = write_tiff(
+            "exif-gps",
+            &[
+                ascii(Tag::GPSDateStamp, In::PRIMARY, "2008:05:30"),
+                gps_time(6, 56, 15, 10),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifGps);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.subsec_nanos, Some(500_000_000));
+        assert_eq!(date.offset, FixedOffset::east_opt(0));
+        assert_eq!(date.raw.as_deref(), Some("2008:05:30 06:56:01.5"));
+    }
+
+    #[test]
+    fn gps_inferred_offset() {
+        // the GPS fix is 11 seconds older than the shot, and the camera is on UTC+9
+        let path = write_tiff(
+            "gps-inferred-offset",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2008:05:30 15:56:01"),
+                ascii(Tag::GPSDateStamp, In::PRIMARY, "2008:05:30"),
+                gps_time(6, 55, 50, 1),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+        assert!(date.offset_inferred);
+        assert_eq!(date.timestamp, 1212162961 - 9 * 3600);
+
+        // a camera clock that was never set is left alone
+        let path = write_tiff(
+            "gps-unset-clock",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2000:01:01 00:00:00"),
+                ascii(Tag::GPSDateStamp, In::PRIMARY, "2008:05:30"),
+                gps_time(6, 55, 50, 1),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.offset, None);
+        assert!(!date.offset_inferred);
+    }
+
+    #[test]
+    #[cfg(feature = "tz-lookup")]
+    fn gps_location_offset() {
+        let dms = |degrees: u32, minutes: u32| vec![(degrees, 1), (minutes, 1), (0, 1)];
+        let rational = |tag: Tag, values: Vec<(u32, u32)>| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(values.into_iter().map(Rational::from).collect()),
+        };
+        let path = write_tiff(
+            "gps-location-offset",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2008:05:30 15:56:01"),
+                rational(Tag::GPSLatitude, dms(35, 41)),
+                ascii(Tag::GPSLatitudeRef, In::PRIMARY, "N"),
+                rational(Tag::GPSLongitude, dms(139, 46)),
+                ascii(Tag::GPSLongitudeRef, In::PRIMARY, "E"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+        assert!(date.offset_inferred);
+        assert_eq!(date.timestamp, 1212162961 - 9 * 3600);
+    }
+
+    #[test]
+    fn exif_lenient() {
+        let path = write_tiff(
+            "exif-lenient",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "0000:00:00 00:00:00"),
+                ascii(Tag::DateTimeDigitized, In::PRIMARY, "2008/05/30 03:56 PM"),
+            ],
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifCreateDate);
+        assert_eq!(date.timestamp, 1212162960);
+        assert!(date.repaired);
+
+        let path = write_tiff(
+            "exif-malformed",
+            &[ascii(Tag::DateTimeOriginal, In::PRIMARY, "yesterday")],
+        );
+        let date = try_get_image_date_with(&path, &without_directories()).unwrap();
+        assert!(date.source >= DateSource::SysCreated);
+    }
+
+    #[test]
+    fn policy() {
+        let path = write_tiff(
+            "policy",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2001:01:01 01:01:01"),
+                ascii(Tag::DateTime, In::PRIMARY, "2003:03:03 03:03:03"),
+            ],
+        );
+        let filename = &path;
+
+        let policy = DatePolicy::new().order(&[DateSource::ExifModifyDate]);
+        let date = try_get_image_date_with(filename, &policy).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+
+        let policy = DatePolicy::new().disable(DateSource::ExifDateTimeOriginal);
+        let date = try_get_image_date_with(filename, &policy).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+
+        let mut policy = without_directories();
+        for source in EXIF_SOURCES.iter() {
+            policy = policy.disable(*source);
+        }
+        let date = try_get_image_date_with(filename, &policy).unwrap();
+        assert!(date.source >= DateSource::SysCreated);
+
+        let policy = without_directories()
+            .disable(DateSource::ExifDateTimeOriginal)
+            .disable(DateSource::ExifModifyDate)
+            .disable(DateSource::SysCreated)
+            .disable(DateSource::SysModified)
+            .disable(DateSource::SysAccessed);
+        match try_get_image_date_with(filename, &policy) {
+            Err(ImageDateError::NoSource) => (),
+            other => panic!("Expected no source, got {:?}", other),
+        }
+        assert_eq!(get_image_date_with(filename, &policy), ERR_DATE);
+    }
+
+    #[test]
+    fn all_dates() {
+        let path = write_tiff(
+            "all-dates",
+            &[
+                ascii(Tag::DateTimeOriginal, In::PRIMARY, "2001:01:01 01:01:01"),
+                ascii(Tag::DateTimeDigitized, In::PRIMARY, "yesterday"),
+                ascii(Tag::DateTime, In::PRIMARY, "2003:03:03 03:03:03"),
+            ],
+        );
+        let candidates = all_image_dates(&path).unwrap();
+        let tags: Vec<&str> = candidates
+            .iter()
+            .map(|candidate| candidate.tag.as_str())
+            .collect();
+        assert_eq!(
+            &tags[..3],
+            &["DateTimeOriginal", "DateTimeDigitized", "DateTime"]
+        );
+        assert!(tags.contains(&"modified"));
+
+        assert_eq!(candidates[0].source, DateSource::ExifDateTimeOriginal);
+        assert!(candidates[0].date.is_ok());
+        match &candidates[1].date {
+            Err(ImageDateError::MalformedDate(raw)) => assert_eq!(raw, "yesterday"),
+            other => panic!("Expected a malformed date, got {:?}", other),
+        }
+        assert_eq!(candidates[2].date.as_ref().unwrap().timestamp, 1046660583);
+
+        // the same order and sources as the policy selects with
+        let policy = DatePolicy::new()
+            .order(&[DateSource::ExifModifyDate])
+            .disable(DateSource::ExifDateTimeOriginal);
+        let candidates = all_image_dates_with(&path, &policy).unwrap();
+        assert_eq!(candidates[0].tag, "DateTime");
+        assert!(candidates
+            .iter()
+            .all(|candidate| candidate.source != DateSource::ExifDateTimeOriginal));
+        let date = try_get_image_date_with(&path, &policy).unwrap();
+        assert_eq!(date.timestamp, 1046660583);
+    }
+
+    #[test]
+    fn in_memory() {
+        let path = write_tiff(
+            "in-memory",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "2008:05:30 15:56:01",
+            )],
+        );
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(get_image_date_from_bytes(&bytes), 1212162961);
+
+        let mut reader = Cursor::new(&bytes);
+        let date = try_get_image_date_from_reader(&mut reader).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+
+        // without a file there are no filesystem times to fall back on
+        match try_get_image_date_from_bytes(b"not really an image") {
+            Err(ImageDateError::UnreadableContainer(_)) => (),
+            other => panic!("Expected an unreadable container, got {:?}", other),
+        }
+        let policy = DatePolicy::new().disable(DateSource::ExifDateTimeOriginal);
+        match try_get_image_date_from_reader_with(&mut Cursor::new(&bytes), &policy) {
+            Err(ImageDateError::NoSource) => (),
+            other => panic!("Expected no source, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn png_dates() {
+        let exif = tiff(&[ascii(Tag::DateTime, In::PRIMARY, "2003:03:03 03:03:03")]);
+        let file = png::build::png(&[
+            png::build::chunk(b"eXIf", &exif),
+            png::build::chunk(b"tEXt", b"Creation Time\0Fri, 30 May 2008 15:56:01 +0900"),
+            png::build::chunk(b"tEXt", b"Creation Time\0sometime in May"),
+        ]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifModifyDate);
+        assert_eq!(date.timestamp, 1046660583);
+
+        let policy = DatePolicy::new().disable(DateSource::ExifModifyDate);
+        let date = try_get_image_date_from_reader_with(&mut Cursor::new(&file), &policy).unwrap();
+        assert_eq!(date.source, DateSource::PngCreationTime);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+
+        let path = write_temp("png-dates.png", &file);
+        let candidates = all_image_dates(&path).unwrap();
+        let tags: Vec<&str> = candidates
+            .iter()
+            .map(|candidate| candidate.tag.as_str())
+            .collect();
+        assert_eq!(
+            &tags[..3],
+            &["DateTime", "tEXt Creation Time", "tEXt Creation Time"]
+        );
+        assert!(candidates[2].date.is_err());
+
+        // a PNG with only a text date, no eXIf chunk
+        let file = png::build::png(&[png::build::chunk(
+            b"tEXt",
+            b"Creation Time\x002008-05-30T15:56:01Z",
+        )]);
+        assert_eq!(get_image_date_from_bytes(&file), 1212162961);
+    }
+
+    #[test]
+    fn xmp_sidecar() {
+        // Lightroom replaces the extension
+        let path = write_tiff(
+            "lightroom",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "2008:05:30 15:56:01",
+            )],
+        );
+        write_temp(
+            "lightroom.xmp",
+            br#"<rdf:Description xmp:CreateDate="2008-05-30T15:56:01+09:00"/>"#,
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+
+        let policy = DatePolicy::new().sidecar_overrides(true);
+        let date = try_get_image_date_with(&path, &policy).unwrap();
+        assert_eq!(date.source, DateSource::XmpSidecar);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+
+        // darktable appends to it, and its sidecar is the one read when both are there
+        let path = write_temp("darktable.cr2", b"not really a raw file");
+        write_temp(
+            "darktable.cr2.xmp",
+            b"<exif:DateTimeOriginal>2008-05-30T15:56:01Z</exif:DateTimeOriginal>",
+        );
+        write_temp(
+            "darktable.xmp",
+            b"<exif:DateTimeOriginal>2001-01-01T01:01:01Z</exif:DateTimeOriginal>",
+        );
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::XmpSidecar);
+        assert_eq!(date.timestamp, 1212162961);
+
+        let candidates = all_image_dates(&path).unwrap();
+        assert_eq!(candidates[0].tag, "exif:DateTimeOriginal");
+        let policy = DatePolicy::new().disable(DateSource::XmpSidecar);
+        let date = try_get_image_date_with(&path, &policy).unwrap();
+        assert_ne!(date.source, DateSource::XmpSidecar);
+    }
+
+    #[test]
+    fn xmp_embedded() {
+        let packet = |date: &str| {
+            format!(
+                r#"<x:xmpmeta><rdf:RDF><rdf:Description xmp:CreateDate="{}"/></rdf:RDF></x:xmpmeta>"#,
+                date
+            )
+        };
+
+        // a JPEG with no Exif data at all
+        let mut app1 = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        app1.extend_from_slice(packet("2008-05-30T15:56:01+09:00").as_bytes());
+        let file = jpeg::build::jpeg(&[jpeg::build::segment(jpeg::APP1, &app1)]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::XmpEmbedded);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.precision, Precision::Second);
+
+        // TIFF tag 700, behind the Exif dates
+        let xml_packet = Field {
+            tag: Tag(exif::Context::Tiff, 0x02bc),
+            ifd_num: In::PRIMARY,
+            value: Value::Byte(packet("2008-05").into_bytes()),
+        };
+        let file = tiff(&[
+            ascii(Tag::DateTime, In::PRIMARY, "2003:03:03 03:03:03"),
+            xml_packet,
+        ]);
+        assert_eq!(get_image_date_from_bytes(&file), 1046660583);
+        let policy = DatePolicy::new().disable(DateSource::ExifModifyDate);
+        let date = try_get_image_date_from_reader_with(&mut Cursor::new(&file), &policy).unwrap();
+        assert_eq!(date.source, DateSource::XmpEmbedded);
+        assert_eq!(date.precision, Precision::Month);
+        assert_eq!(date.raw.as_deref(), Some("2008-05"));
+        assert_eq!(date.timestamp, 1209600000);
+
+        // PNG iTXt
+        let mut itxt = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+        itxt.extend_from_slice(packet("2008-05-30").as_bytes());
+        let file = png::build::png(&[png::build::chunk(b"iTXt", &itxt)]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.precision, Precision::Day);
+        assert_eq!(date.timestamp, 1212105600);
+
+        // WebP
+        let chunk = webp::build::chunk(b"XMP ", packet("2008").as_bytes());
+        let file = webp::build::webp(&[chunk]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::XmpEmbedded);
+        assert_eq!(date.precision, Precision::Year);
+        assert_eq!(date.timestamp, 1199145600);
+    }
+
+    #[test]
+    fn iptc_dates() {
+        let mut iim = iptc::build::dataset(iptc::DATE_CREATED, b"20080530");
+        iim.extend(iptc::build::dataset(iptc::TIME_CREATED, b"155601+0900"));
+        let file = jpeg::build::jpeg(&[iptc::build::app13(&iim)]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::IptcDateCreated);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+        assert_eq!(date.raw.as_deref(), Some("20080530 155601+0900"));
+
+        // only the date
+        let iim = iptc::build::dataset(iptc::DATE_CREATED, b"20080530");
+        let file = jpeg::build::jpeg(&[iptc::build::app13(&iim)]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.timestamp, 1212105600);
+        assert_eq!(date.precision, Precision::Day);
+
+        let iim = iptc::build::dataset(iptc::DATE_CREATED, b"30.05.2008");
+        let file = jpeg::build::jpeg(&[iptc::build::app13(&iim)]);
+        match try_get_image_date_from_bytes(&file) {
+            Err(ImageDateError::MalformedDate(raw)) => assert_eq!(raw, "30.05.2008"),
+            other => panic!("Expected a malformed date, got {:?}", other),
+        }
+
+        // an APP13 segment that is cut short, or too short to hold its own length, after the Exif
+        // data
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff(&[ascii(
+            Tag::DateTimeOriginal,
+            In::PRIMARY,
+            "2008:05:30 15:56:01",
+        )]));
+        let app1 = jpeg::build::segment(jpeg::APP1, &app1);
+        let mut iim = iptc::build::dataset(iptc::DATE_CREATED, b"20080530");
+        iim.extend(iptc::build::dataset(iptc::TIME_CREATED, b"155601+0900"));
+        let app13 = iptc::build::app13(&iim);
+        let mut cut = vec![0xff, 0xd8];
+        cut.extend_from_slice(&app1);
+        cut.extend_from_slice(&app13[..app13.len() - 8]);
+        let short = jpeg::build::jpeg(&[app1, vec![0xff, 0xed, 0x00, 0x01]]);
+        for file in [cut, short].iter() {
+            let date = try_get_image_date_from_bytes(file).unwrap();
+            assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+            assert_eq!(date.timestamp, 1212162961);
+        }
+    }
+
+    // A reader whose first read after its `seeks`th seek back to the start fails, as a flaky disk or
+    // network share might
+    struct FlakyReader {
+        inner: Cursor<Vec<u8>>,
+        seeks_left: Option<usize>,
+    }
+
+    impl FlakyReader {
+        fn new(data: Vec<u8>, seeks: usize) -> FlakyReader {
+            FlakyReader {
+                inner: Cursor::new(data),
+                seeks_left: Some(seeks),
+            }
+        }
+
+        fn fail(&mut self) -> io::Result<()> {
+            if self.seeks_left == Some(0) {
+                self.seeks_left = None;
+                return Err(io::Error::new(io::ErrorKind::Other, "flaky"));
+            }
+            Ok(())
+        }
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.fail()?;
+            self.inner.read(buf)
+        }
+    }
+
+    impl BufRead for FlakyReader {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            self.fail()?;
+            self.inner.fill_buf()
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.inner.consume(amt)
+        }
+    }
+
+    impl Seek for FlakyReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            if pos == SeekFrom::Start(0) {
+                self.seeks_left = self.seeks_left.map(|seeks| seeks.saturating_sub(1));
+            }
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut app1 = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        app1.extend_from_slice(br#"<rdf:Description xmp:CreateDate="2001-01-01"/>"#);
+        let iim = iptc::build::dataset(iptc::DATE_CREATED, b"20080530");
+        let file = jpeg::build::jpeg(&[
+            jpeg::build::segment(jpeg::APP1, &app1),
+            iptc::build::app13(&iim),
+        ]);
+
+        // the XMP packet cannot be read, which does not stop the IPTC dates from being read
+        let mut reader = FlakyReader::new(file, 2);
+        let date = try_get_image_date_from_reader(&mut reader).unwrap();
+        assert_eq!(date.source, DateSource::IptcDateCreated);
+        assert_eq!(reader.seeks_left, None);
+
+        // nor does failing to read the IPTC dates lose the XMP ones
+        let mut reader = FlakyReader::new(reader.inner.into_inner(), 3);
+        let date = try_get_image_date_from_reader(&mut reader).unwrap();
+        assert_eq!(date.source, DateSource::XmpEmbedded);
+        assert_eq!(reader.seeks_left, None);
+    }
+
+    #[test]
+    fn video_dates() {
+        use isobmff::build::*;
+
+        // 2008-05-30 06:56:01 UTC, in seconds since 1904
+        let created = 1212130561 + 2082844800;
+        let mut moov = quicktime::build::header(b"mvhd", 0, created);
+        let mut day = vec![0, 4, 0x15, 0xc7];
+        day.extend_from_slice(b"2008");
+        moov.extend(boxed(b"udta", &boxed(b"\xa9day", &day)));
+        moov.extend(quicktime::build::keyed(
+            "com.apple.quicktime.creationdate",
+            "2008-05-30T15:56:01+0900",
+        ));
+        let mut file = ftyp(b"isom", &[b"isom", b"mp41"]);
+        file.extend(boxed(b"mdat", &[0; 64]));
+        file.extend(boxed(b"moov", &moov));
+
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::QuickTimeCreationDate);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.offset, FixedOffset::east_opt(9 * 3600));
+
+        let policy = DatePolicy::new().disable(DateSource::QuickTimeCreationDate);
+        let date = try_get_image_date_from_reader_with(&mut Cursor::new(&file), &policy).unwrap();
+        assert_eq!(date.source, DateSource::QuickTimeDay);
+        assert_eq!(date.precision, Precision::Year);
+
+        // an old QuickTime movie, with no file type box and only the movie header
+        let file = boxed(b"moov", &quicktime::build::header(b"mvhd", 0, created));
+        let path = write_temp("video-dates.mov", &file);
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::QuickTimeCreateDate);
+        assert_eq!(date.timestamp, 1212130561);
+        assert_eq!(date.offset, Some(Utc.fix()));
+        let candidates = all_image_dates(&path).unwrap();
+        assert_eq!(candidates[0].tag, "mvhd");
+    }
+
+    #[test]
+    fn webp_and_jxl_dates() {
+        let exif = tiff(&[ascii(
+            Tag::DateTimeOriginal,
+            In::PRIMARY,
+            "2008:05:30 15:56:01",
+        )]);
+        let file = webp::build::webp(&[webp::build::chunk(b"EXIF", &exif)]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+
+        let path = write_temp("jxl-dates.jxl", &jxl::build::jxl(&exif, 2));
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+    }
+
+    #[test]
+    fn filename_dates() {
+        let path = write_temp("IMG-20200101-WA0001.jpg", b"stripped by a messenger");
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::FileName);
+        assert_eq!(date.timestamp, 1577836800);
+        assert_eq!(date.precision, Precision::Day);
+        assert_eq!(date.raw.as_deref(), Some("imagedt-IMG-20200101-WA0001.jpg"));
+
+        let policy = DatePolicy::new().filename_pattern("imagedt-IMG-%Y%m*");
+        let date = try_get_image_date_with(&path, &policy).unwrap();
+        assert_eq!(date.source, DateSource::FileName);
+        assert_eq!(date.precision, Precision::Month);
+
+        // the metadata still wins
+        let path = write_tiff(
+            "IMG_20200101_123456",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "2008:05:30 15:56:01",
+            )],
+        );
+        let candidates = all_image_dates(&path).unwrap();
+        assert_eq!(candidates[0].source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(candidates[1].source, DateSource::FileName);
+        assert_eq!(candidates[1].date.as_ref().unwrap().timestamp, 1577882096);
+    }
+
+    #[test]
+    fn directory_dates() {
+        let dir = std::env::temp_dir().join("imagedt-directory-dates/2009/2009-07-14 Wedding");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("DSC0001.JPG");
+        fs::write(&path, b"no metadata").unwrap();
+
+        let date = try_get_image_date(&path).unwrap();
+        assert_eq!(date.source, DateSource::DirectoryName);
+        assert_eq!(date.timestamp, 1247529600);
+        assert_eq!(date.precision, Precision::Day);
+        assert_eq!(date.raw.as_deref(), Some("2009-07-14 Wedding"));
+
+        // a date in the file name is nearer the mark
+        let path = dir.join("IMG_20090714_153000.jpg");
+        fs::write(&path, b"no metadata").unwrap();
+        let candidates = all_image_dates(&path).unwrap();
+        assert_eq!(candidates[0].source, DateSource::FileName);
+        assert_eq!(candidates[1].source, DateSource::DirectoryName);
+        assert!(candidates[2].source >= DateSource::SysCreated);
+    }
+
+    #[test]
+    fn raf_and_x3f_dates() {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff(&[ascii(
+            Tag::DateTimeOriginal,
+            In::PRIMARY,
+            "2008:05:30 15:56:01",
+        )]));
+        let preview = jpeg::build::jpeg(&[jpeg::build::segment(jpeg::APP1, &app1)]);
+        let file = raf::build::raf(&preview);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+        assert_eq!(date.timestamp, 1212162961);
+
+        let file = x3f::build::x3f(&[("TIME", "1212162961")]);
+        let date = try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, DateSource::X3fTime);
+        assert_eq!(date.timestamp, 1212162961);
+        assert_eq!(date.raw.as_deref(), Some("1212162961"));
+
+        let file = x3f::build::x3f(&[("TIME", "yesterday")]);
+        match try_get_image_date_from_bytes(&file) {
+            Err(ImageDateError::MalformedDate(raw)) => assert_eq!(raw, "yesterday"),
+            other => panic!("Expected a malformed date, got {:?}", other),
+        }
+
+        let file = x3f::build::x3f(&[("CAMMANUF", "SIGMA")]);
+        match try_get_image_date_from_bytes(&file) {
+            Err(ImageDateError::NoSource) => (),
+            other => panic!("Expected no date source, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let tiff = write_tiff(
+            "non-utf8",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "2008:05:30 15:56:01",
+            )],
+        );
+        let path = std::env::temp_dir().join(OsStr::from_bytes(b"imagedt-non-utf8-\xff.tif"));
+        fs::copy(&tiff, &path).unwrap();
+        assert_eq!(get_image_date(&path), 1212162961);
+    }
+
+    #[test]
+    fn exif_before_epoch() {
+        let path = write_tiff(
+            "exif-before-epoch",
+            &[ascii(
+                Tag::DateTimeOriginal,
+                In::PRIMARY,
+                "1965:07:14 12:00:00",
+            )],
+        );
+        assert_eq!(get_image_date(&path), -140961600);
+    }
+
+    #[test]
+    fn system_time_before_epoch() {
+        let time = UNIX_EPOCH - std::time::Duration::new(140961600, 250_000_000);
+        let date = ImageDate::from_system_time(DateSource::SysModified, time).unwrap();
+        assert_eq!(date.timestamp, -140961601);
+        assert_eq!(date.subsec_nanos, Some(750_000_000));
+        assert_eq!(date.date_time.to_string(), "1965-07-14 11:59:59.750 +00:00");
+    }
+
+    #[test]
+    fn filesystem_source() {
+        let path = write_temp("filesystem-source.jpg", b"not really an image");
+        let date = try_get_image_date_with(&path, &without_directories()).unwrap();
+        assert!(date.source >= DateSource::SysCreated);
+        assert_eq!(date.raw, None);
+        assert_eq!(
+            date.subsec_nanos,
+            Some(date.date_time.timestamp_subsec_nanos())
+        );
+    }
+
+    #[allow(clippy::needless_return)]
     fn is_file_image(filename: &str) -> bool {
         let ext = Path::new(filename).extension();
         let ext = match ext {
@@ -206,6 +2076,24 @@ mod tests {
             "png" => return true,
             "bmp" => return true,
             "cr2" => return true,
+            "cr3" => return true,
+            "nef" => return true,
+            "arw" => return true,
+            "dng" => return true,
+            "orf" => return true,
+            "rw2" => return true,
+            "pef" => return true,
+            "srw" => return true,
+            "raf" => return true,
+            "x3f" => return true,
+            "webp" => return true,
+            "jxl" => return true,
+            "heic" => return true,
+            "heif" => return true,
+            "avif" => return true,
+            "mp4" => return true,
+            "mov" => return true,
+            "3gp" => return true,
             _ => return false, // extension does not match anything above
         }
     }
@@ -213,6 +2101,12 @@ mod tests {
     #[test]
     // To see the time result of this function you have to run cargo test as such:
     // cargo test -- --nocapture
+    #[allow(
+        non_fmt_panics,
+        clippy::bool_comparison,
+        clippy::assertions_on_constants,
+        clippy::needless_borrows_for_generic_args
+    )]
     fn image_search() {
         let directory = "d:\\pictures";
         let target = Path::new(&directory);
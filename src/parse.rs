@@ -0,0 +1,481 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use chrono::DateTime;
+use chrono::FixedOffset;
+use chrono::NaiveDate;
+use chrono::NaiveDateTime;
+use chrono::NaiveTime;
+
+use crate::Precision;
+
+/// The outcome of leniently parsing a date string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lenient {
+    /// The string was exactly in the expected format.
+    Exact(NaiveDateTime),
+    /// The string was malformed, but in a way that has only one reasonable reading.
+    Repaired(NaiveDateTime),
+    /// The string is a placeholder meaning "unknown", and should be treated as absent.
+    Placeholder,
+    /// The string could not be read as a date at all.
+    Malformed,
+}
+
+/// A date read from free-form text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TextDate {
+    /// The time as written, with any fields left out taken to be at their start.
+    pub(crate) local: NaiveDateTime,
+    /// The offset from UTC `local` was written in, if the text says.
+    pub(crate) offset: Option<FixedOffset>,
+    /// The last field the text gave.
+    pub(crate) precision: Precision,
+}
+
+impl TextDate {
+    pub(crate) fn new(
+        local: NaiveDateTime,
+        offset: Option<FixedOffset>,
+        precision: Precision,
+    ) -> TextDate {
+        TextDate {
+            local,
+            offset,
+            precision,
+        }
+    }
+}
+
+/// Parses an Exif date, which the spec. says is "YYYY:MM:DD HH:MM:SS", tolerating the ways real
+/// files get it wrong:
+///
+/// 1. trailing NULs and blanks, from writers that pad a fixed size field,
+/// 2. all-zero or blank placeholders such as "0000:00:00 00:00:00", which mean unknown,
+/// 3. a 12-hour clock with an AM or PM suffix,
+/// 4. slashes, dashes or dots instead of colons in the date, and a `T` between date and time,
+/// 5. fields padded with blanks instead of zeros, such as "2008: 5:30  9:56:01",
+/// 6. no seconds, such as "2008:05:30 15:56", which are taken to be zero.
+///
+/// The year must still come first and have four digits, so nothing ambiguous is ever guessed at.
+pub(crate) fn parse_exif_date(raw: &str) -> Lenient {
+    // chrono also accepts blank padded fields, so the layout is checked first
+    let exact_layout = raw.len() == 19
+        && raw.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 | 13 | 16 => b == b':',
+            10 => b == b' ',
+            _ => b.is_ascii_digit(),
+        });
+    if exact_layout {
+        if let Ok(date_time) = NaiveDateTime::parse_from_str(raw, "%Y:%m:%d %H:%M:%S") {
+            return Lenient::Exact(date_time);
+        }
+    }
+
+    // rule 1
+    let trimmed = raw.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+
+    // rule 2
+    if trimmed.chars().all(|c| c == '0' || ":-/. ".contains(c)) {
+        return Lenient::Placeholder;
+    }
+
+    // rule 3
+    let (trimmed, pm) = match trimmed.get(trimmed.len().saturating_sub(2)..) {
+        Some(suffix) if suffix.eq_ignore_ascii_case("AM") => {
+            (trimmed[..trimmed.len() - 2].trim_end(), Some(false))
+        }
+        Some(suffix) if suffix.eq_ignore_ascii_case("PM") => {
+            (trimmed[..trimmed.len() - 2].trim_end(), Some(true))
+        }
+        _ => (trimmed, None),
+    };
+
+    // rules 4 and 5, everything but the digits has to be a separator
+    if !trimmed
+        .chars()
+        .all(|c| c.is_ascii_digit() || ":-/.T ".contains(c))
+    {
+        return Lenient::Malformed;
+    }
+    let fields: Vec<&str> = trimmed
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|field| !field.is_empty())
+        .collect();
+
+    // rule 6
+    if fields.len() != 5 && fields.len() != 6 {
+        return Lenient::Malformed;
+    }
+    if fields[0].len() != 4 || fields[1..].iter().any(|field| field.len() > 2) {
+        return Lenient::Malformed;
+    }
+    let numbers = parse_fields(&fields);
+
+    let hour = match pm {
+        Some(_) if numbers[3] == 0 || numbers[3] > 12 => return Lenient::Malformed,
+        Some(pm) => numbers[3] % 12 + if pm { 12 } else { 0 },
+        None => numbers[3],
+    };
+    let second = numbers.get(5).copied().unwrap_or(0);
+
+    match NaiveDate::from_ymd_opt(numbers[0] as i32, numbers[1], numbers[2])
+        .and_then(|date| date.and_hms_opt(hour, numbers[4], second))
+    {
+        Some(date_time) => Lenient::Repaired(date_time),
+        None => Lenient::Malformed,
+    }
+}
+
+/// Parses a date stored as free-form text, such as a PNG "Creation Time" or an XMP property.
+/// Accepted are:
+///
+/// 1. RFC 1123 and RFC 2822, such as "Fri, 30 May 2008 15:56:01 +0900", which PNG recommends,
+/// 2. ISO 8601, such as "2008-05-30T15:56:01.5+09:00", down to as little as "2008-05" or "2008"
+///    as XMP allows, with or without the offset,
+/// 3. the output of C's `asctime`, such as "Fri May 30 15:56:01 2008",
+/// 4. anything `parse_exif_date` accepts.
+pub(crate) fn parse_text_date(raw: &str) -> Option<TextDate> {
+    let trimmed = raw.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+
+    // rule 1
+    if let Ok(date_time) = DateTime::parse_from_rfc2822(trimmed) {
+        let offset = Some(*date_time.offset());
+        return Some(TextDate::new(
+            date_time.naive_local(),
+            offset,
+            Precision::Second,
+        ));
+    }
+
+    // rule 2
+    if let Some(date) = parse_iso8601(trimmed) {
+        return Some(date);
+    }
+
+    // rule 3
+    if let Ok(date_time) = NaiveDateTime::parse_from_str(trimmed, "%a %b %e %H:%M:%S %Y") {
+        return Some(TextDate::new(date_time, None, Precision::Second));
+    }
+
+    // rule 4
+    match parse_exif_date(trimmed) {
+        Lenient::Exact(date_time) | Lenient::Repaired(date_time) => {
+            Some(TextDate::new(date_time, None, Precision::Second))
+        }
+        Lenient::Placeholder | Lenient::Malformed => None,
+    }
+}
+
+/// Parses an IPTC IIM date, "CCYYMMDD", and the time that goes with it, if any, "HHMMSS±HHMM".
+/// IIM writes 00 for a month or day that is not known.
+pub(crate) fn parse_iim_date(date: &str, time: Option<&str>) -> Option<TextDate> {
+    if date.len() != 8 || !date.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let numbers: Vec<u32> = [&date[..4], &date[4..6], &date[6..]]
+        .iter()
+        .filter_map(|field| field.parse().ok())
+        .collect();
+    let (precision, month, day) = match (numbers[1], numbers[2]) {
+        (0, 0) => (Precision::Year, 1, 1),
+        (0, _) => return None,
+        (month, 0) => (Precision::Month, month, 1),
+        (month, day) => (Precision::Day, month, day),
+    };
+    let date = NaiveDate::from_ymd_opt(numbers[0] as i32, month, day)?;
+
+    let time = match time.map(str::trim) {
+        Some(time) if precision == Precision::Day && !time.is_empty() => time,
+        _ => {
+            return Some(TextDate::new(
+                date.and_time(NaiveTime::MIN),
+                None,
+                precision,
+            ))
+        }
+    };
+    let (time, offset) = match time.get(6..) {
+        Some("") => (time, None),
+        Some(offset) => (&time[..6], Some(parse_iso_offset(offset)?)),
+        None => return None,
+    };
+    let time = NaiveTime::parse_from_str(time, "%H%M%S").ok()?;
+    Some(TextDate::new(
+        date.and_time(time),
+        offset,
+        Precision::Second,
+    ))
+}
+
+// Parses an ISO 8601 date and time, in the extended format XMP uses: "YYYY", "YYYY-MM",
+// "YYYY-MM-DD" or "YYYY-MM-DDThh:mm", then optionally ":ss" and a fraction, and an offset of "Z",
+// "+hh:mm", "+hhmm" or "+hh". A blank may stand in for the "T". Only a time can have an offset.
+fn parse_iso8601(raw: &str) -> Option<TextDate> {
+    let (date, time) = match raw.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (raw, None),
+    };
+
+    let fields: Vec<&str> = date.split('-').collect();
+    let well_formed = fields.len() <= 3
+        && fields[0].len() == 4
+        && fields[1..].iter().all(|field| field.len() == 2)
+        && fields
+            .iter()
+            .all(|field| field.bytes().all(|b| b.is_ascii_digit()));
+    if !well_formed {
+        return None;
+    }
+    let numbers = parse_fields(&fields);
+    let date = NaiveDate::from_ymd_opt(
+        numbers[0] as i32,
+        numbers.get(1).copied().unwrap_or(1),
+        numbers.get(2).copied().unwrap_or(1),
+    )?;
+    let precision = match numbers.len() {
+        1 => Precision::Year,
+        2 => Precision::Month,
+        _ => Precision::Day,
+    };
+    let time = match time {
+        Some(time) if precision == Precision::Day => time,
+        Some(_) => return None,
+        None => {
+            return Some(TextDate::new(
+                date.and_time(NaiveTime::MIN),
+                None,
+                precision,
+            ))
+        }
+    };
+
+    let (time, offset) = match time.strip_suffix('Z') {
+        Some(time) => (time, FixedOffset::east_opt(0)),
+        None => match time.rfind(['+', '-']) {
+            Some(sign) => (&time[..sign], Some(parse_iso_offset(&time[sign..])?)),
+            None => (time, None),
+        },
+    };
+    let (time, precision) = match NaiveTime::parse_from_str(time, "%H:%M:%S%.f") {
+        Ok(time) => (time, Precision::Second),
+        Err(_) => (
+            NaiveTime::parse_from_str(time, "%H:%M").ok()?,
+            Precision::Minute,
+        ),
+    };
+    Some(TextDate::new(date.and_time(time), offset, precision))
+}
+
+// Parses fields that have been checked to be runs of digits, at most four each, so none can fail
+// to parse
+fn parse_fields(fields: &[&str]) -> Vec<u32> {
+    fields
+        .iter()
+        .filter_map(|field| field.parse().ok())
+        .collect()
+}
+
+// Parses an ISO 8601 offset from UTC, sign included
+fn parse_iso_offset(raw: &str) -> Option<FixedOffset> {
+    let sign = match raw.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits: String = raw[1..].chars().filter(|&c| c != ':').collect();
+    if !digits.bytes().all(|b| b.is_ascii_digit()) || raw[1..].len() > 5 {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repaired(date_time: &str) -> Lenient {
+        Lenient::Repaired(NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap())
+    }
+
+    #[test]
+    fn exif_date_variants() {
+        let exact = NaiveDateTime::parse_from_str("2008-05-30 15:56:01", "%Y-%m-%d %H:%M:%S");
+        assert_eq!(
+            parse_exif_date("2008:05:30 15:56:01"),
+            Lenient::Exact(exact.unwrap())
+        );
+
+        let cases = [
+            ("2008:05:30 15:56:01\0\0", "2008-05-30 15:56:01"),
+            ("2008:05:30 15:56:01   ", "2008-05-30 15:56:01"),
+            ("2008:05:30 03:56:01 PM", "2008-05-30 15:56:01"),
+            ("2008:05:30 12:56:01am", "2008-05-30 00:56:01"),
+            ("2008/05/30 15:56:01", "2008-05-30 15:56:01"),
+            ("2008-05-30T15:56:01", "2008-05-30 15:56:01"),
+            ("2008.05.30 15:56:01", "2008-05-30 15:56:01"),
+            ("2008: 5:30  9:56: 1", "2008-05-30 09:56:01"),
+            ("2008:05:30 15:56", "2008-05-30 15:56:00"),
+        ];
+        for (raw, expected) in cases.iter() {
+            assert_eq!(parse_exif_date(raw), repaired(expected), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn exif_date_placeholders() {
+        assert_eq!(parse_exif_date("0000:00:00 00:00:00"), Lenient::Placeholder);
+        assert_eq!(parse_exif_date("    :  :     :  :  "), Lenient::Placeholder);
+        assert_eq!(parse_exif_date("\0\0\0\0"), Lenient::Placeholder);
+        assert_eq!(parse_exif_date(""), Lenient::Placeholder);
+    }
+
+    #[test]
+    fn exif_date_malformed() {
+        let cases = [
+            "2008:02:30 15:56:01",  // no such day
+            "08:05:30 15:56:01",    // two digit year
+            "30/05/2008 15:56:01",  // day first
+            "2008:05:30",           // no time
+            "2008:05:30 13:56 PM",  // not a 12-hour time
+            "2008:05:30 15:56:01Z", // not a separator
+            "yesterday",
+        ];
+        for raw in cases.iter() {
+            assert_eq!(parse_exif_date(raw), Lenient::Malformed, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn text_date_formats() {
+        let local = NaiveDateTime::parse_from_str("2008-05-30 15:56:01", "%Y-%m-%d %H:%M:%S");
+        let local = local.unwrap();
+        let tokyo = FixedOffset::east_opt(9 * 3600);
+        let utc = FixedOffset::east_opt(0);
+        let cases = [
+            ("Fri, 30 May 2008 15:56:01 +0900", tokyo),
+            ("30 May 2008 15:56:01 GMT", utc),
+            ("2008-05-30T15:56:01+09:00", tokyo),
+            ("2008-05-30 15:56:01Z", utc),
+            ("2008-05-30T15:56:01+0900", tokyo),
+            ("2008-05-30T15:56:01+09", tokyo),
+            ("2008-05-30T15:56:01", None),
+            ("Fri May 30 15:56:01 2008\n", None),
+            ("2008:05:30 15:56:01", None),
+        ];
+        for (raw, offset) in cases.iter() {
+            let expected = TextDate::new(local, *offset, Precision::Second);
+            assert_eq!(parse_text_date(raw), Some(expected), "{:?}", raw);
+        }
+
+        let fraction = parse_text_date("2008-05-30T15:56:01.25").unwrap();
+        assert_eq!(fraction.local.and_utc().timestamp_subsec_millis(), 250);
+        assert_eq!(parse_text_date("last summer"), None);
+        assert_eq!(parse_text_date("2008-05-30T15:56:01+25:00"), None);
+    }
+
+    #[test]
+    fn partial_dates() {
+        let date = |raw: &str| NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S").unwrap();
+        let cases = [
+            ("2008", "2008-01-01 00:00:00", None, Precision::Year),
+            ("2008-05", "2008-05-01 00:00:00", None, Precision::Month),
+            ("2008-05-30", "2008-05-30 00:00:00", None, Precision::Day),
+            (
+                "2008-05-30T15:56-04:30",
+                "2008-05-30 15:56:00",
+                FixedOffset::west_opt(4 * 3600 + 1800),
+                Precision::Minute,
+            ),
+        ];
+        for (raw, local, offset, precision) in cases.iter() {
+            let expected = TextDate::new(date(local), *offset, *precision);
+            assert_eq!(parse_text_date(raw), Some(expected), "{:?}", raw);
+        }
+
+        let malformed = [
+            "200",
+            "2008-5",
+            "2008-13",
+            "2008-05T15:56",
+            "2008-05-30+09:00",
+        ];
+        for raw in malformed.iter() {
+            assert_eq!(parse_text_date(raw), None, "{:?}", raw);
+        }
+        assert_eq!(parse_text_date("0000:00:00 00:00:00"), None);
+    }
+
+    #[test]
+    fn iim_dates() {
+        let date = |raw: &str| NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S").unwrap();
+        let tokyo = FixedOffset::east_opt(9 * 3600);
+        let cases = [
+            (
+                "20080530",
+                Some("155601+0900"),
+                "2008-05-30 15:56:01",
+                tokyo,
+                Precision::Second,
+            ),
+            (
+                "20080530",
+                Some("155601"),
+                "2008-05-30 15:56:01",
+                None,
+                Precision::Second,
+            ),
+            (
+                "20080530",
+                None,
+                "2008-05-30 00:00:00",
+                None,
+                Precision::Day,
+            ),
+            (
+                "20080500",
+                Some("155601"),
+                "2008-05-01 00:00:00",
+                None,
+                Precision::Month,
+            ),
+            (
+                "20080000",
+                None,
+                "2008-01-01 00:00:00",
+                None,
+                Precision::Year,
+            ),
+        ];
+        for (raw, time, local, offset, precision) in cases.iter() {
+            let expected = TextDate::new(date(local), *offset, *precision);
+            assert_eq!(parse_iim_date(raw, *time), Some(expected), "{:?}", raw);
+        }
+
+        assert_eq!(parse_iim_date("2008-05-30", None), None);
+        assert_eq!(parse_iim_date("20080230", None), None);
+        assert_eq!(parse_iim_date("20080530", Some("15:56:01")), None);
+        assert_eq!(parse_iim_date("20080530", Some("155601 0900")), None);
+    }
+}
@@ -0,0 +1,404 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Matching file and directory names against date patterns, templates in the style of `strftime`
+// with shell-like wildcards. See `DatePolicy::filename_pattern` for the syntax.
+
+use std::path::Component;
+use std::path::Path;
+
+use chrono::Datelike;
+use chrono::NaiveDate;
+use chrono::Utc;
+
+use crate::parse::TextDate;
+use crate::Precision;
+
+/// The patterns of the file names cameras, phones and apps give their images, most specific first.
+pub(crate) const FILENAME_PATTERNS: [&str; 6] = [
+    // IMG_20200101_123456.jpg, VID_..., PXL_20211003_201512345.jpg
+    "*%Y%m%d_%H%M%S*",
+    // Screenshot_2020-05-03-14-22-11.png
+    "*%Y-%m-%d-%H-%M-%S*",
+    // signal-2021-02-03-101010.jpg
+    "*%Y-%m-%d-%H%M%S*",
+    // 2020-05-03 14.22.11.jpg, as Dropbox uploads them
+    "*%Y-%m-%d?%H.%M.%S*",
+    // Screenshot 2020-05-03 at 14.22.11.png, from macOS
+    "*%Y-%m-%d at %H.%M.%S*",
+    // IMG-20200101-WA0001.jpg, from WhatsApp, which only keeps the date
+    "*%Y%m%d-WA*",
+];
+
+/// The patterns of the names archives give their directories, most specific first. A `/` separates
+/// the names of a directory and its parents, for archives filed by year, then month, then day.
+pub(crate) const DIRECTORY_PATTERNS: [&str; 10] = [
+    // 2009/07/14/ and 2009/07/
+    "%Y/%m/%d",
+    "%Y/%m",
+    // 2009-07-14 Wedding/, 2009_07_14/, 2009.07.14/
+    "%Y-%m-%d*",
+    "%Y_%m_%d*",
+    "%Y.%m.%d*",
+    // 2009-07 Holiday/
+    "%Y-%m*",
+    "%Y_%m*",
+    // 1998/ and 1998 Summer/
+    "%Y",
+    "%Y *",
+    "%Y-*",
+];
+
+// the most directories a pattern can span
+const MAX_DEPTH: usize = 3;
+
+// the most directories above a file searched for a date, as those further up are more likely named
+// for where the archive is kept, such as a home directory or mount point, than for when
+const MAX_LEVELS: usize = 4;
+
+// the year of the oldest surviving photograph, before which a name cannot hold the date one was taken
+const FIRST_YEAR: u32 = 1826;
+
+// the fields a pattern can match, in the order of `Precision`
+const FIELDS: [(char, usize); 6] = [('Y', 4), ('m', 2), ('d', 2), ('H', 2), ('M', 2), ('S', 2)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// The index of a field in `FIELDS`.
+    Field(usize),
+    /// Any run of characters, including none.
+    Any,
+    /// Any one character.
+    One,
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => Token::Any,
+            '?' => Token::One,
+            '%' => match chars.next() {
+                Some(directive) => match FIELDS.iter().position(|&(c, _)| c == directive) {
+                    Some(field) => Token::Field(field),
+                    None => Token::Literal(directive), // %%, %* and %? included
+                },
+                None => Token::Literal('%'),
+            },
+            c => Token::Literal(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Returns the last year a name can hold the date of a photograph in, allowing for a camera clock
+/// set a little ahead. It is worked out once for each lookup, and passed to the matching functions.
+pub(crate) fn last_year() -> u32 {
+    // the clock is after the year 0, so the cast cannot wrap
+    (Utc::now().year() + 1) as u32
+}
+
+/// Matches the whole of `name` against `pattern`, returning the date it spells out. A name that
+/// fits the pattern but not the calendar, such as a 13th month, does not match, and nor does a year
+/// before `FIRST_YEAR` or after `last_year`.
+pub(crate) fn match_pattern(pattern: &str, name: &str, last_year: u32) -> Option<TextDate> {
+    let tokens = tokenize(pattern);
+    let name: Vec<char> = name.chars().collect();
+    let mut matcher = Matcher {
+        tokens: &tokens,
+        name: &name,
+        last_year,
+        values: [None; 6],
+        failed: vec![false; (tokens.len() + 1) * (name.len() + 1)],
+    };
+    if !matcher.match_tokens(0, 0) {
+        return None;
+    }
+    let values = matcher.values;
+
+    // each field only counts if the coarser ones are there too
+    let recorded = values.iter().take_while(|value| value.is_some()).count();
+    let precision = match recorded {
+        0 => return None,
+        1 => Precision::Year,
+        2 => Precision::Month,
+        3 => Precision::Day,
+        4 => Precision::Hour,
+        5 => Precision::Minute,
+        _ => Precision::Second,
+    };
+    let value = |field: usize, default: u32| match values[field] {
+        Some(value) if field < recorded => value,
+        _ => default,
+    };
+    let local = NaiveDate::from_ymd_opt(value(0, 0) as i32, value(1, 1), value(2, 1))?
+        .and_hms_opt(value(3, 0), value(4, 0), value(5, 0))?;
+    Some(TextDate::new(local, None, precision))
+}
+
+/// Matches the directories `path` is in, nearest first and at most `MAX_LEVELS` of them, against
+/// `DIRECTORY_PATTERNS`. Returns the pattern and the directory, with its parents if the pattern spans
+/// them, of the first match.
+pub(crate) fn match_directories(
+    path: &Path,
+    last_year: u32,
+) -> Option<(&'static str, String, TextDate)> {
+    let mut names: Vec<String> = Vec::new();
+    for component in path.parent()?.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => (),
+        }
+    }
+    let names = &names[names.len().saturating_sub(MAX_LEVELS)..];
+
+    for end in (1..=names.len()).rev() {
+        // the pattern spanning the most directories gives the most precise date
+        for depth in (1..=MAX_DEPTH.min(end)).rev() {
+            let directories = names[end - depth..end].join("/");
+            for pattern in DIRECTORY_PATTERNS.iter() {
+                if pattern.matches('/').count() != depth - 1 {
+                    continue;
+                }
+                if let Some(date) = match_pattern(pattern, &directories, last_year) {
+                    return Some((pattern, directories, date));
+                }
+            }
+        }
+    }
+    None
+}
+
+// One match of a pattern's tokens against a name
+struct Matcher<'a> {
+    tokens: &'a [Token],
+    name: &'a [char],
+    last_year: u32,
+    // the fields of the match found so far
+    values: [Option<u32>; 6],
+    // for each token and position in the name, whether the rest of the tokens are known not to
+    // match the rest of the name, so that wildcards leading there again give up at once
+    failed: Vec<bool>,
+}
+
+impl Matcher<'_> {
+    // Backtracks through the ways the tokens from `token` on could match the name from `at` on,
+    // recording the fields of the first that does
+    fn match_tokens(&mut self, token: usize, at: usize) -> bool {
+        let state = token * (self.name.len() + 1) + at;
+        if self.failed[state] {
+            return false;
+        }
+        let matched = self.match_token(token, at);
+        self.failed[state] = !matched;
+        matched
+    }
+
+    fn match_token(&mut self, token: usize, at: usize) -> bool {
+        let name = &self.name[at..];
+        let next = match self.tokens.get(token) {
+            Some(&next) => next,
+            None => return name.is_empty(),
+        };
+        match next {
+            Token::Literal(c) => match name.first() {
+                Some(first) if first.eq_ignore_ascii_case(&c) => {
+                    self.match_tokens(token + 1, at + 1)
+                }
+                _ => false,
+            },
+            Token::One => !name.is_empty() && self.match_tokens(token + 1, at + 1),
+            Token::Field(field) => {
+                let width = FIELDS[field].1;
+                let digits = match name.get(..width) {
+                    Some(digits) if digits.iter().all(char::is_ascii_digit) => digits,
+                    _ => return false,
+                };
+                let value = digits.iter().fold(0, |value, &digit| {
+                    value * 10 + digit.to_digit(10).unwrap_or(0)
+                });
+                // a serial number or placeholder rather than a year, so it may yet be found
+                // elsewhere
+                if field == 0 && !(FIRST_YEAR..=self.last_year).contains(&value) {
+                    return false;
+                }
+                self.values[field] = Some(value);
+                if self.match_tokens(token + 1, at + width) {
+                    return true;
+                }
+                self.values[field] = None;
+                false
+            }
+            Token::Any => (at..=self.name.len()).any(|start| {
+                // a date does not start in the middle of a number
+                let splits_number = start > at
+                    && self.name[start - 1].is_ascii_digit()
+                    && matches!(self.tokens.get(token + 1), Some(Token::Field(_)));
+                !splits_number && self.match_tokens(token + 1, start)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the last year a photograph could be from, pinned so the tests do not depend on the clock
+    const LAST_YEAR: u32 = 2025;
+
+    // Matches `name` against the built-in patterns, as the file name source does
+    fn match_filename(name: &str) -> Option<(String, Precision)> {
+        FILENAME_PATTERNS.iter().find_map(|pattern| {
+            match_pattern(pattern, name, LAST_YEAR)
+                .map(|date| (date.local.to_string(), date.precision))
+        })
+    }
+
+    #[test]
+    fn filename_patterns() {
+        let dates = [
+            ("IMG_20200101_123456.jpg", "2020-01-01 12:34:56"),
+            ("PXL_20211003_201512345.jpg", "2021-10-03 20:15:12"),
+            ("Screenshot_2020-05-03-14-22-11.png", "2020-05-03 14:22:11"),
+            ("signal-2021-02-03-101010.jpg", "2021-02-03 10:10:10"),
+            ("2020-05-03 14.22.11.jpg", "2020-05-03 14:22:11"),
+            (
+                "Screenshot 2020-05-03 at 14.22.11.png",
+                "2020-05-03 14:22:11",
+            ),
+        ];
+        for (name, date) in dates.iter() {
+            assert_eq!(
+                match_filename(name),
+                Some((date.to_string(), Precision::Second)),
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            match_filename("IMG-20200101-WA0001.jpg"),
+            Some(("2020-01-01 00:00:00".to_string(), Precision::Day))
+        );
+
+        for name in [
+            "DSC_0001.JPG",
+            "IMG_20201301_123456.jpg",
+            "120200101_123456.jpg",
+            "IMG_99991231_235959.jpg",
+            "IMG_18000101_000000.jpg",
+        ]
+        .iter()
+        {
+            assert_eq!(match_filename(name), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn directory_patterns() {
+        let dates = [
+            (
+                "2009/2009-07-14 Wedding/DSC0001.JPG",
+                "2009-07-14",
+                Precision::Day,
+            ),
+            ("Photos/1998/Summer/scan.jpg", "1998", Precision::Year),
+            (
+                "/archive/2009/07/14/DSC0001.JPG",
+                "2009/07/14",
+                Precision::Day,
+            ),
+            (
+                "archive/2009/07/Holiday/DSC0001.JPG",
+                "2009/07",
+                Precision::Month,
+            ),
+            (
+                "2010 Trips/2009-07 Rome/../Misc/a.jpg",
+                "2010 Trips",
+                Precision::Year,
+            ),
+        ];
+        for (path, directories, precision) in dates.iter() {
+            let (_, matched, date) = match_directories(Path::new(path), LAST_YEAR).unwrap();
+            assert!(matched.starts_with(directories), "{}", path);
+            assert_eq!(date.precision, *precision, "{}", path);
+        }
+        let (_, _, date) =
+            match_directories(Path::new("2009-07-14 Wedding/a.jpg"), LAST_YEAR).unwrap();
+        assert_eq!(date.local.to_string(), "2009-07-14 00:00:00");
+
+        // only the nearest directories count
+        let path = Path::new("2009/Rome/Day 1/Edited/a.jpg");
+        assert!(match_directories(path, LAST_YEAR).is_some());
+        let path = Path::new("/mnt/2009/Rome/Day 1/Edited/Final/a.jpg");
+        assert!(match_directories(path, LAST_YEAR).is_none());
+
+        for path in [
+            "photo.jpg",
+            "Photos/Summer/a.jpg",
+            "DCIM/100CANON/a.jpg",
+            // a serial number rather than a year
+            "9999/a.jpg",
+            "1234 Scans/a.jpg",
+        ]
+        .iter()
+        {
+            assert!(
+                match_directories(Path::new(path), LAST_YEAR).is_none(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn pattern_syntax() {
+        let date =
+            match_pattern("holiday %Y-%m *", "Holiday 2019-08 beach.jpg", LAST_YEAR).unwrap();
+        assert_eq!(date.local.to_string(), "2019-08-01 00:00:00");
+        assert_eq!(date.precision, Precision::Month);
+
+        // a field after a missing one does not count
+        let date = match_pattern("%Y-%d.jpg", "2019-21.jpg", LAST_YEAR).unwrap();
+        assert_eq!(date.local.to_string(), "2019-01-01 00:00:00");
+        assert_eq!(date.precision, Precision::Year);
+
+        assert!(match_pattern("100%%_%Y*", "100%_2019.jpg", LAST_YEAR).is_some());
+        assert!(match_pattern("%Y", "2019.jpg", LAST_YEAR).is_none());
+        assert!(match_pattern("photo.jpg", "photo.jpg", LAST_YEAR).is_none());
+
+        // years up to the last one given
+        assert!(match_pattern("%Y*", "2025.jpg", LAST_YEAR).is_some());
+        assert!(match_pattern("%Y*", "2026.jpg", LAST_YEAR).is_none());
+        assert!(match_pattern("%Y*", "2026.jpg", 2026).is_some());
+        assert!(match_pattern("%Y*", "1826.jpg", LAST_YEAR).is_some());
+        assert!(match_pattern("%Y*", "1825.jpg", LAST_YEAR).is_none());
+    }
+
+    #[test]
+    fn many_wildcards() {
+        // without remembering where matching failed, each `*` multiplies the ways to try
+        let pattern = "*a*a*a*a*a*a*a*a*a*a*a*a*%Y";
+        let name = "a".repeat(200);
+        assert!(match_pattern(pattern, &name, LAST_YEAR).is_none());
+        let date = match_pattern(pattern, &format!("{}2019", name), LAST_YEAR).unwrap();
+        assert_eq!(date.local.to_string(), "2019-01-01 00:00:00");
+    }
+}
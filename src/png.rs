@@ -0,0 +1,253 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// PNG keeps Exif attributes in an `eXIf` chunk, and text such as a "Creation Time" in `tEXt`,
+// `zTXt` and `iTXt` chunks, any of which may come before or after the image data.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::invalid_data;
+use crate::util::MAX_CHUNK_LEN;
+
+const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Returns whether `head`, the start of a file, is the start of a PNG file.
+pub(crate) fn is_png(head: &[u8]) -> bool {
+    head.starts_with(SIGNATURE)
+}
+
+/// Reads the TIFF structure in the `eXIf` chunk of the PNG file `reader` is at the start of.
+pub(crate) fn get_exif_attr<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let mut chunks = Vec::new();
+    let read = read_chunks(reader, &[b"eXIf"], &mut chunks);
+    if chunks.is_empty() {
+        read?;
+        return Err(exif::Error::NotFound("PNG"));
+    }
+    let (_, mut data) = chunks.swap_remove(0);
+    // some writers keep the prefix of the JPEG APP1 segment
+    if data.starts_with(b"Exif\0\0") {
+        data.drain(..6);
+    }
+    Ok(data)
+}
+
+/// Adds the type of chunk and the text of every text chunk with the given keyword in the PNG file
+/// `reader` is at the start of to `texts`. Chunks that are corrupt or in an unknown compression
+/// are skipped. The texts found before an error are kept.
+pub(crate) fn get_text<R: Read + Seek>(
+    reader: &mut R,
+    keyword: &str,
+    texts: &mut Vec<([u8; 4], String)>,
+) -> io::Result<()> {
+    let mut chunks = Vec::new();
+    let read = read_chunks(reader, &[b"tEXt", b"zTXt", b"iTXt"], &mut chunks);
+    texts.extend(chunks.into_iter().filter_map(|(kind, data)| {
+        let (found, text) = parse_text(&kind, &data)?;
+        if found == keyword {
+            Some((kind, text))
+        } else {
+            None
+        }
+    }));
+    read
+}
+
+// Returns the keyword and text of a text chunk
+fn parse_text(kind: &[u8; 4], data: &[u8]) -> Option<(String, String)> {
+    let mut fields = data.splitn(2, |&b| b == 0);
+    let keyword = latin1(fields.next()?);
+    let rest = fields.next()?;
+
+    let text = match kind {
+        b"tEXt" => latin1(rest),
+        // a compression method, which can only be 0 for zlib
+        b"zTXt" => match rest.split_first()? {
+            (0, compressed) => latin1(&inflate(compressed)?),
+            _ => return None,
+        },
+        // a compression flag and method, then the language and translated keyword
+        b"iTXt" => {
+            let (&compressed, rest) = rest.split_first()?;
+            let (&method, rest) = rest.split_first()?;
+            let mut fields = rest.splitn(3, |&b| b == 0);
+            fields.next()?;
+            fields.next()?;
+            let text = fields.next()?;
+            match (compressed, method) {
+                (0, _) => String::from_utf8(text.to_vec()).ok()?,
+                (1, 0) => String::from_utf8(inflate(text)?).ok()?,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some((keyword, text))
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn inflate(compressed: &[u8]) -> Option<Vec<u8>> {
+    miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, MAX_CHUNK_LEN as usize).ok()
+}
+
+// Adds the type and data of every chunk of one of the given types to `chunks`, seeking over the
+// others, until the end of the file. The CRCs are not checked, as a date is still worth having
+// from a chunk that was damaged.
+fn read_chunks<R: Read + Seek>(
+    reader: &mut R,
+    kinds: &[&[u8; 4]],
+    chunks: &mut Vec<([u8; 4], Vec<u8>)>,
+) -> io::Result<()> {
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature)?;
+    if signature != *SIGNATURE {
+        return Err(invalid_data("not a PNG file"));
+    }
+
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            // a file cut short after its metadata still has the metadata
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err),
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let kind = [header[4], header[5], header[6], header[7]];
+        if kind == *b"IEND" {
+            break;
+        }
+
+        if kinds.contains(&&kind) {
+            if len > MAX_CHUNK_LEN {
+                return Err(invalid_data("chunk is too large"));
+            }
+            let mut data = Vec::new();
+            reader.take(u64::from(len)).read_to_end(&mut data)?;
+            if data.len() < len as usize {
+                break;
+            }
+            chunks.push((kind, data));
+            reader.seek(SeekFrom::Current(4))?;
+        } else {
+            reader.seek(SeekFrom::Current(i64::from(len) + 4))?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds PNG files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    pub(crate) fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&[0; 4]); // CRC, which is not checked
+        chunk
+    }
+
+    // A PNG file with the given chunks around a 1x1 image
+    pub(crate) fn png(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = super::SIGNATURE.to_vec();
+        file.extend(chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        for data in chunks {
+            file.extend_from_slice(data);
+        }
+        file.extend(chunk(b"IEND", &[]));
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    #[test]
+    fn text_chunks() {
+        let mut ztxt = b"Creation Time\0\0".to_vec();
+        ztxt.extend(compress_to_vec_zlib(b"2008-05-30T15:56:01", 6));
+        let mut itxt = b"Creation Time\0\x01\0en\0Erstellungszeit\0".to_vec();
+        itxt.extend(compress_to_vec_zlib(
+            "30 May 2008 15:56:01 GMT".as_bytes(),
+            6,
+        ));
+        let file = png(&[
+            chunk(b"tEXt", b"Creation Time\0Fri, 30 May 2008 15:56:01 +0900"),
+            chunk(b"tEXt", b"Software\0Paint \xe9dition"),
+            chunk(b"IDAT", &[0; 12]),
+            chunk(b"zTXt", &ztxt),
+            chunk(b"iTXt", &itxt),
+            chunk(b"zTXt", b"Creation Time\0\x07unknown method"),
+        ]);
+        assert!(is_png(&file));
+
+        let mut texts = Vec::new();
+        get_text(&mut Cursor::new(&file), "Creation Time", &mut texts).unwrap();
+        let expected = vec![
+            (*b"tEXt", "Fri, 30 May 2008 15:56:01 +0900".to_string()),
+            (*b"zTXt", "2008-05-30T15:56:01".to_string()),
+            (*b"iTXt", "30 May 2008 15:56:01 GMT".to_string()),
+        ];
+        assert_eq!(texts, expected);
+        let mut texts = Vec::new();
+        get_text(&mut Cursor::new(&file), "Software", &mut texts).unwrap();
+        assert_eq!(texts[0].1, "Paint \u{e9}dition");
+    }
+
+    #[test]
+    fn text_before_corrupt_chunk() {
+        let mut file = png(&[chunk(b"tEXt", b"Creation Time\x002008-05-30T15:56:01")]);
+        // a chunk claiming more than any text chunk could hold, in place of IEND
+        file.truncate(file.len() - 12);
+        file.extend_from_slice(&u32::MAX.to_be_bytes());
+        file.extend_from_slice(b"tEXt");
+
+        let mut texts = Vec::new();
+        assert!(get_text(&mut Cursor::new(&file), "Creation Time", &mut texts).is_err());
+        assert_eq!(texts, vec![(*b"tEXt", "2008-05-30T15:56:01".to_string())]);
+
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.source, crate::DateSource::PngCreationTime);
+        assert_eq!(date.timestamp, 1212162961);
+    }
+
+    #[test]
+    fn exif_chunk() {
+        let file = png(&[chunk(b"eXIf", b"MM\0\x2a"), chunk(b"IDAT", &[0; 12])]);
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), b"MM\0\x2a");
+        let file = png(&[
+            chunk(b"IDAT", &[0; 12]),
+            chunk(b"eXIf", b"Exif\0\0II\x2a\0"),
+        ]);
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), b"II\x2a\0");
+
+        let file = png(&[chunk(b"IDAT", &[0; 12])]);
+        match get_exif_attr(&mut Cursor::new(&file)) {
+            Err(exif::Error::NotFound(_)) => (),
+            other => panic!("Expected no eXIf chunk, got {:?}", other),
+        }
+        assert!(!is_png(b"\xff\xd8\xff\xe1"));
+    }
+}
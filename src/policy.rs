@@ -0,0 +1,244 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::pattern::FILENAME_PATTERNS;
+use crate::DateSource;
+use crate::EXIF_SOURCES;
+
+/// Every source, in the default order of priority.
+const DEFAULT_ORDER: [DateSource; 17] = [
+    DateSource::ExifDateTimeOriginal,
+    DateSource::ExifGps,
+    DateSource::ExifCreateDate,
+    DateSource::ExifModifyDate,
+    DateSource::XmpSidecar,
+    DateSource::XmpEmbedded,
+    DateSource::IptcDateCreated,
+    DateSource::QuickTimeCreationDate,
+    DateSource::QuickTimeDay,
+    DateSource::QuickTimeCreateDate,
+    DateSource::PngCreationTime,
+    DateSource::X3fTime,
+    DateSource::FileName,
+    DateSource::DirectoryName,
+    DateSource::SysCreated,
+    DateSource::SysModified,
+    DateSource::SysAccessed,
+];
+
+/// Decides which sources may provide an image's date, and which of them wins when several do.
+///
+/// The default policy enables every source, in the order `DateSource` declares them.
+///
+/// ```
+/// use imagedt::{DatePolicy, DateSource};
+///
+/// // prefer the modification time over the creation time, and never trust the access time
+/// let policy = DatePolicy::new()
+///     .order(&[DateSource::SysModified, DateSource::SysCreated])
+///     .disable(DateSource::SysAccessed);
+/// let date = imagedt::get_image_date_with("photo.jpg", &policy);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatePolicy {
+    order: Vec<DateSource>,
+    disabled: Vec<DateSource>,
+    filename_patterns: Vec<String>,
+}
+
+impl Default for DatePolicy {
+    fn default() -> DatePolicy {
+        DatePolicy {
+            order: DEFAULT_ORDER.to_vec(),
+            disabled: Vec::new(),
+            filename_patterns: Vec::new(),
+        }
+    }
+}
+
+impl DatePolicy {
+    /// Creates the default policy.
+    pub fn new() -> DatePolicy {
+        DatePolicy::default()
+    }
+
+    /// Ranks `sources` first, in the order given. Sources left out keep their relative order after
+    /// them, so nothing is disabled by omission.
+    pub fn order(mut self, sources: &[DateSource]) -> DatePolicy {
+        let mut order = Vec::with_capacity(DEFAULT_ORDER.len());
+        for &source in sources.iter().chain(self.order.iter()) {
+            if !order.contains(&source) {
+                order.push(source);
+            }
+        }
+        self.order = order;
+        self
+    }
+
+    /// Whether an XMP sidecar overrides the Exif data embedded in the image, which it does not by
+    /// default. Sidecars are where photo managers keep corrections, so a library edited in one is
+    /// best read with this on. Moves `DateSource::XmpSidecar` just ahead of the highest ranked Exif
+    /// source, or just behind the lowest.
+    pub fn sidecar_overrides(mut self, overrides: bool) -> DatePolicy {
+        self.order
+            .retain(|&source| source != DateSource::XmpSidecar);
+        let exif = self
+            .order
+            .iter()
+            .enumerate()
+            .filter(|(_, source)| EXIF_SOURCES.contains(source))
+            .map(|(i, _)| i);
+        let at = if overrides {
+            exif.min().unwrap_or(0)
+        } else {
+            exif.max().map_or(0, |i| i + 1)
+        };
+        self.order.insert(at, DateSource::XmpSidecar);
+        self
+    }
+
+    /// Adds a pattern for `DateSource::FileName` to match file names against, tried before the
+    /// built-in ones and those added earlier. The pattern must match the whole file name, extension
+    /// included, in which:
+    ///
+    /// - `%Y` matches a four digit year from `FIRST_YEAR` (1826) up to next year, and `%m`, `%d`,
+    ///   `%H`, `%M` and `%S` the two digit month, day, hour, minute and second,
+    /// - `*` matches any run of characters, including none, though not one that ends inside the
+    ///   number a field starts,
+    /// - `?` matches any one character,
+    /// - `%` followed by anything else matches that character, so `%%` matches a `%`,
+    /// - every other character matches itself, ignoring ASCII case.
+    ///
+    /// Fields left out, or after one that is, make the date less precise, so `IMG_%Y%m%d*` only
+    /// gives the day. A pattern without `%Y` never matches.
+    ///
+    /// ```
+    /// use imagedt::DatePolicy;
+    ///
+    /// // Holiday 2019-08-14 #12.jpg
+    /// let policy = DatePolicy::new().filename_pattern("Holiday %Y-%m-%d #*");
+    /// ```
+    pub fn filename_pattern(mut self, pattern: &str) -> DatePolicy {
+        self.filename_patterns.insert(0, pattern.to_string());
+        self
+    }
+
+    /// The patterns `DateSource::FileName` tries, in order.
+    pub(crate) fn filename_patterns(&self) -> impl Iterator<Item = &str> {
+        self.filename_patterns
+            .iter()
+            .map(String::as_str)
+            .chain(FILENAME_PATTERNS.iter().copied())
+    }
+
+    /// Allows `source` to provide the date again.
+    pub fn enable(mut self, source: DateSource) -> DatePolicy {
+        self.disabled.retain(|&disabled| disabled != source);
+        self
+    }
+
+    /// Stops `source` from providing the date, wherever it is ranked.
+    pub fn disable(mut self, source: DateSource) -> DatePolicy {
+        if !self.disabled.contains(&source) {
+            self.disabled.push(source);
+        }
+        self
+    }
+
+    /// Whether `source` may provide the date.
+    pub fn is_enabled(&self, source: DateSource) -> bool {
+        !self.disabled.contains(&source)
+    }
+
+    /// The enabled sources, highest priority first.
+    pub fn sources(&self) -> impl Iterator<Item = DateSource> + '_ {
+        self.order
+            .iter()
+            .copied()
+            .filter(move |&source| self.is_enabled(source))
+    }
+
+    /// Whether any of `sources` may provide the date.
+    pub(crate) fn any_enabled(&self, sources: &[DateSource]) -> bool {
+        sources.iter().any(|&source| self.is_enabled(source))
+    }
+
+    /// The position of `source` in the order, lower is better.
+    pub(crate) fn rank(&self, source: DateSource) -> usize {
+        self.order
+            .iter()
+            .position(|&ranked| ranked == source)
+            .unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_and_switches() {
+        let policy = DatePolicy::new()
+            .order(&[DateSource::SysModified, DateSource::ExifModifyDate])
+            .disable(DateSource::SysAccessed)
+            .disable(DateSource::ExifGps)
+            .enable(DateSource::ExifGps);
+        let sources: Vec<DateSource> = policy.sources().collect();
+        // the rest keep their default order
+        assert_eq!(
+            &sources[..5],
+            &[
+                DateSource::SysModified,
+                DateSource::ExifModifyDate,
+                DateSource::ExifDateTimeOriginal,
+                DateSource::ExifGps,
+                DateSource::ExifCreateDate,
+            ]
+        );
+        assert_eq!(sources.last(), Some(&DateSource::SysCreated));
+        assert!(!sources.contains(&DateSource::SysAccessed));
+        assert!(policy.rank(DateSource::SysModified) < policy.rank(DateSource::SysCreated));
+        assert!(!policy.is_enabled(DateSource::SysAccessed));
+    }
+
+    #[test]
+    fn filename_patterns() {
+        let policy = DatePolicy::new()
+            .filename_pattern("first %Y*")
+            .filename_pattern("second %Y*");
+        let patterns: Vec<&str> = policy.filename_patterns().collect();
+        assert_eq!(&patterns[..2], &["second %Y*", "first %Y*"]);
+        assert_eq!(patterns.len(), 2 + FILENAME_PATTERNS.len());
+    }
+
+    #[test]
+    fn sidecar_overrides() {
+        let policy = DatePolicy::new().sidecar_overrides(true);
+        assert_eq!(policy.sources().next(), Some(DateSource::XmpSidecar));
+        let policy = policy.sidecar_overrides(false);
+        assert_eq!(policy, DatePolicy::new());
+
+        let policy = DatePolicy::new()
+            .order(&[DateSource::SysModified, DateSource::ExifGps])
+            .sidecar_overrides(true);
+        let sources: Vec<DateSource> = policy.sources().take(3).collect();
+        assert_eq!(
+            sources,
+            vec![
+                DateSource::SysModified,
+                DateSource::XmpSidecar,
+                DateSource::ExifGps,
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// QuickTime movies, and the MP4 and 3GP files descended from them, describe their contents in a
+// `moov` box: the movie and track headers record when they were created, and the metadata, in
+// either Apple's keyed form or the older `udta` atoms, may record it as text.
+
+use std::convert::TryFrom;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+
+use crate::isobmff;
+use crate::isobmff::Boxes;
+use crate::isobmff::Bytes;
+
+/// The largest `moov` box read. The sample tables of a long recording make for a large one, but not
+/// this large, and a CR3's holds little more than the metadata and a thumbnail.
+pub(crate) const MAX_MOVIE_LEN: u64 = 64 * 1024 * 1024;
+
+/// Seconds from 1904-01-01, the QuickTime epoch, to the Unix epoch.
+const EPOCH_1904: i64 = 2_082_844_800;
+
+// brands of QuickTime, MP4 and 3GP movies, or the start of them, as some carry a version
+const BRANDS: [&[u8]; 11] = [
+    b"qt  ", b"iso", b"mp4", b"mp7", b"3gp", b"3g2", b"M4V", b"M4A", b"avc1", b"f4v ", b"XAVC",
+];
+
+// brands of files built like movies that are not, and that also claim a movie brand
+const NOT_MOVIES: [&[u8; 4]; 1] = [b"crx "];
+
+// the boxes a QuickTime movie without a file type box may start with
+const FIRST_BOXES: [&[u8; 4]; 6] = [b"moov", b"mdat", b"wide", b"free", b"skip", b"pnot"];
+
+/// Returns whether `head`, the start of a file, is the start of a QuickTime, MP4 or 3GP movie.
+/// HEIF files are built the same way, and may claim the same brands, so must be told apart first.
+pub(crate) fn is_quicktime(head: &[u8]) -> bool {
+    match isobmff::brands(head) {
+        Some(brands) => {
+            !NOT_MOVIES.contains(&&brands[0])
+                && brands
+                    .iter()
+                    .any(|brand| BRANDS.iter().any(|movie| brand.starts_with(movie)))
+        }
+        None => {
+            let first = head.get(4..8);
+            FIRST_BOXES.iter().any(|kind| first == Some(&kind[..]))
+        }
+    }
+}
+
+/// Reads the body of the `moov` box of the movie `reader` is at the start of.
+pub(crate) fn read_movie<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    isobmff::read_box(reader, b"moov", MAX_MOVIE_LEN)
+}
+
+/// Returns the name of the header and the time in seconds since the Unix epoch of the movie's
+/// creation, from the movie header, or failing that the first track header that records one.
+pub(crate) fn get_creation_time(moov: &[u8]) -> Option<(&'static str, i64)> {
+    let movie = isobmff::find_box(moov, b"mvhd")
+        .and_then(get_header_time)
+        .map(|time| ("mvhd", time));
+    movie.or_else(|| {
+        Boxes::new(moov)
+            .filter(|(kind, _)| kind == b"trak")
+            .filter_map(|(_, trak)| isobmff::find_box(trak, b"tkhd"))
+            .find_map(get_header_time)
+            .map(|time| ("tkhd", time))
+    })
+}
+
+// Reads the creation time of a movie or track header, both of which start with it. Writers that
+// do not know the time leave it 0.
+fn get_header_time(header: &[u8]) -> Option<i64> {
+    let (version, _, body) = isobmff::full_box(header)?;
+    let mut bytes = Bytes::new(body);
+    let created = match version {
+        0 => u64::from(bytes.u32()?),
+        _ => bytes.u64()?,
+    };
+    match created {
+        0 => None,
+        created => i64::try_from(created).ok()?.checked_sub(EPOCH_1904),
+    }
+}
+
+/// Returns the value of the metadata item with the key `key`, e.g.
+/// "com.apple.quicktime.creationdate", from the `meta` box of the movie.
+pub(crate) fn get_metadata(moov: &[u8], key: &str) -> Option<String> {
+    let meta = meta_children(isobmff::find_box(moov, b"meta")?);
+    let keys = isobmff::find_box(meta, b"keys")?;
+    let (_, _, keys) = isobmff::full_box(keys)?;
+    let mut bytes = Bytes::new(keys);
+    let count = bytes.u32()?;
+    // the items refer to their keys by index, counting from 1
+    let mut index = None;
+    for i in 1..=count {
+        // a length, which counts itself, and a namespace, then the key
+        let len = bytes.u32()?;
+        let entry = bytes.take((len as usize).checked_sub(4)?)?;
+        if entry.get(4..) == Some(key.as_bytes()) {
+            index = Some(i);
+            break;
+        }
+    }
+    let index = index?;
+
+    let ilst = isobmff::find_box(meta, b"ilst")?;
+    let item = isobmff::find_box(ilst, &index.to_be_bytes())?;
+    get_data(item)
+}
+
+/// Returns the `©day` atom of the movie's user data, the date the movie was recorded, whether
+/// written as a QuickTime text atom or as an iTunes style item.
+pub(crate) fn get_day(moov: &[u8]) -> Option<String> {
+    let udta = isobmff::find_box(moov, b"udta")?;
+    if let Some(day) = isobmff::find_box(udta, b"\xa9day") {
+        // a length and a language, then the text
+        let mut bytes = Bytes::new(day);
+        let len = bytes.u16()?;
+        bytes.u16()?;
+        let text = bytes.take(usize::from(len))?;
+        return Some(String::from_utf8_lossy(text).into_owned());
+    }
+    let meta = meta_children(isobmff::find_box(udta, b"meta")?);
+    let ilst = isobmff::find_box(meta, b"ilst")?;
+    get_data(isobmff::find_box(ilst, b"\xa9day")?)
+}
+
+// Returns the text in the `data` box of a metadata item
+fn get_data(item: &[u8]) -> Option<String> {
+    let data = isobmff::find_box(item, b"data")?;
+    let mut bytes = Bytes::new(data);
+    let kind = bytes.u32()?;
+    bytes.u32()?; // locale
+    match kind {
+        // UTF-8, with or without a sort order
+        1 | 4 => String::from_utf8(bytes.rest().to_vec()).ok(),
+        _ => None,
+    }
+}
+
+// QuickTime's `meta` box is a plain box, but ISO's is a full box, so which it is has to be told
+// from where its first child, always `hdlr`, starts
+fn meta_children(meta: &[u8]) -> &[u8] {
+    match meta.get(4..8) {
+        Some(b"hdlr") => meta,
+        _ => meta.get(4..).unwrap_or_default(),
+    }
+}
+
+/// Builds movies for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    use crate::isobmff::build::*;
+
+    pub(crate) fn header(kind: &[u8; 4], version: u8, created: u64) -> Vec<u8> {
+        let mut body = match version {
+            0 => (created as u32).to_be_bytes().to_vec(),
+            _ => created.to_be_bytes().to_vec(),
+        };
+        body.extend_from_slice(&[0; 16]);
+        full_boxed(kind, version, &body)
+    }
+
+    pub(crate) fn data(text: &str) -> Vec<u8> {
+        let mut body = vec![0, 0, 0, 1, 0, 0, 0, 0];
+        body.extend_from_slice(text.as_bytes());
+        boxed(b"data", &body)
+    }
+
+    // A QuickTime style meta box holding one keyed item
+    pub(crate) fn keyed(key: &str, text: &str) -> Vec<u8> {
+        let mut keys = 2u32.to_be_bytes().to_vec();
+        for key in &["com.apple.quicktime.make", key] {
+            keys.extend_from_slice(&(key.len() as u32 + 8).to_be_bytes());
+            keys.extend_from_slice(b"mdta");
+            keys.extend_from_slice(key.as_bytes());
+        }
+        let mut meta = full_boxed(b"hdlr", 0, &[0; 21]);
+        meta.extend(full_boxed(b"keys", 0, &keys));
+        meta.extend(boxed(b"ilst", &boxed(&2u32.to_be_bytes(), &data(text))));
+        boxed(b"meta", &meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use crate::isobmff::build::*;
+
+    #[test]
+    fn headers() {
+        // 2008-05-30 15:56:01 UTC
+        let created = 1212162961 + EPOCH_1904 as u64;
+        let moov = header(b"mvhd", 0, created);
+        assert_eq!(get_creation_time(&moov), Some(("mvhd", 1212162961)));
+        let moov = header(b"mvhd", 1, created);
+        assert_eq!(get_creation_time(&moov), Some(("mvhd", 1212162961)));
+
+        // no movie time, so the tracks'
+        let mut moov = header(b"mvhd", 0, 0);
+        moov.extend(boxed(b"trak", &header(b"tkhd", 0, 0)));
+        moov.extend(boxed(b"trak", &header(b"tkhd", 0, created)));
+        assert_eq!(get_creation_time(&moov), Some(("tkhd", 1212162961)));
+        assert_eq!(get_creation_time(&header(b"mvhd", 0, 0)), None);
+
+        // before the Unix epoch, but after QuickTime's
+        let moov = header(b"mvhd", 0, 86400);
+        assert_eq!(get_creation_time(&moov), Some(("mvhd", 86400 - EPOCH_1904)));
+    }
+
+    #[test]
+    fn metadata() {
+        let key = "com.apple.quicktime.creationdate";
+        let moov = keyed(key, "2008-05-30T15:56:01+0900");
+        assert_eq!(
+            get_metadata(&moov, key).as_deref(),
+            Some("2008-05-30T15:56:01+0900")
+        );
+        assert_eq!(get_metadata(&moov, "com.apple.quicktime.model"), None);
+
+        // QuickTime text atom
+        let mut day = vec![0, 10, 0x15, 0xc7];
+        day.extend_from_slice(b"2008-05-30");
+        let moov = boxed(b"udta", &boxed(b"\xa9day", &day));
+        assert_eq!(get_day(&moov).as_deref(), Some("2008-05-30"));
+
+        // iTunes style item, in an ISO meta box
+        let mut meta = full_boxed(b"hdlr", 0, &[0; 21]);
+        meta.extend(boxed(b"ilst", &boxed(b"\xa9day", &data("2008"))));
+        let moov = boxed(b"udta", &full_boxed(b"meta", 0, &meta));
+        assert_eq!(get_day(&moov).as_deref(), Some("2008"));
+    }
+
+    #[test]
+    fn movie_files() {
+        assert!(is_quicktime(&ftyp(b"qt  ", &[b"qt  "])));
+        assert!(is_quicktime(&ftyp(b"3gp4", &[b"isom", b"3gp4"])));
+        assert!(is_quicktime(&boxed(b"moov", &[])));
+        assert!(!is_quicktime(b"\xff\xd8\xff\xe1\0\0\0\0"));
+        // Canon's CR3 and JPEG XL are built from boxes too
+        assert!(!is_quicktime(&ftyp(b"crx ", &[b"crx ", b"isom"])));
+        assert!(!is_quicktime(&ftyp(b"jxl ", &[b"jxl "])));
+        assert!(!is_quicktime(b"\0\0\0\x0cJXL \r\n\x87\n"));
+    }
+}
@@ -0,0 +1,100 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Fujifilm's RAF starts with a header of its own, which gives where the embedded JPEG preview,
+// and with it the Exif attributes, is.
+
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::isobmff::Bytes;
+
+const MAGIC: &[u8; 16] = b"FUJIFILMCCD-RAW ";
+
+// where in the header the offset and length of the JPEG are
+const JPEG_POINTER: usize = 84;
+
+// a preview, not the full image, so anything larger is corrupt
+const MAX_JPEG_LEN: u32 = 64 * 1024 * 1024;
+
+/// Returns whether `head`, the start of a file, is the start of a RAF file.
+pub(crate) fn is_raf(head: &[u8]) -> bool {
+    head.starts_with(MAGIC)
+}
+
+/// Reads the JPEG preview of the RAF file `reader` is at the start of.
+pub(crate) fn get_jpeg<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let start = reader.stream_position()?;
+    let mut header = [0; JPEG_POINTER + 8];
+    reader.read_exact(&mut header)?;
+
+    let mut bytes = Bytes::new(&header[JPEG_POINTER..]);
+    let offset = bytes.u32().unwrap_or_default();
+    let len = bytes.u32().unwrap_or_default();
+    if offset == 0 || len == 0 {
+        return Err(exif::Error::NotFound("RAF"));
+    }
+    if len > MAX_JPEG_LEN {
+        return Err(exif::Error::InvalidFormat("RAF preview is too large"));
+    }
+
+    reader.seek(SeekFrom::Start(start + u64::from(offset)))?;
+    let mut jpeg = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut jpeg)?;
+    Ok(jpeg)
+}
+
+/// Builds RAF files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    // A RAF file wrapping `jpeg`
+    pub(crate) fn raf(jpeg: &[u8]) -> Vec<u8> {
+        let mut file = super::MAGIC.to_vec();
+        file.extend_from_slice(b"0201FF129502");
+        file.extend_from_slice(&[0; 32]); // the camera's name
+        file.extend_from_slice(b"0100");
+        file.extend_from_slice(&[0; 20]);
+        file.extend_from_slice(&148u32.to_be_bytes());
+        file.extend_from_slice(&(jpeg.len() as u32).to_be_bytes());
+        file.resize(148, 0);
+        file.extend_from_slice(jpeg);
+        file.extend_from_slice(&[0; 16]); // the CFA
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn preview() {
+        let file = raf(b"\xff\xd8\xff\xd9");
+        assert!(is_raf(&file));
+        assert_eq!(
+            get_jpeg(&mut Cursor::new(&file)).unwrap(),
+            b"\xff\xd8\xff\xd9"
+        );
+
+        let mut file = raf(b"");
+        file[JPEG_POINTER..JPEG_POINTER + 4].copy_from_slice(&[0; 4]);
+        match get_jpeg(&mut Cursor::new(&file)) {
+            Err(exif::Error::NotFound(_)) => (),
+            other => panic!("Expected no preview, got {:?}", other),
+        }
+    }
+}
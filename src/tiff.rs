@@ -0,0 +1,287 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Most raw formats (NEF, ARW, DNG, PEF, SRW, CR2, ORF, RW2) are TIFF files underneath. Olympus and
+// Panasonic change the magic number, which is put back before the file is parsed, and some writers
+// leave the Exif IFD out of IFD0 and only point to it from one of the sub-IFDs.
+
+use std::io::Cursor;
+
+use exif::experimental::Writer;
+use exif::Field;
+use exif::In;
+use exif::Reader;
+
+/// The magic numbers of TIFF and its vendor variants, and the standard one each stands in for.
+const MAGIC: [(&[u8; 4], &[u8; 4]); 6] = [
+    (b"II*\0", b"II*\0"),
+    (b"MM\0*", b"MM\0*"),
+    (b"IIRO", b"II*\0"),  // Olympus ORF
+    (b"IIRS", b"II*\0"),  // Olympus ORF, older models
+    (b"MMOR", b"MM\0*"),  // Olympus ORF, big endian
+    (b"IIU\0", b"II*\0"), // Panasonic RW2 and RAW
+];
+
+// 0x014a SubIFDs (offsets of the IFDs holding the full size and other images)
+const SUB_IFDS: exif::Tag = exif::Tag(exif::Context::Tiff, 0x014a);
+// the IFD field type, which kamadak-exif leaves unparsed
+const IFD_TYPE: u16 = 13;
+// a raw file has a sub-IFD for each of the few images it holds, so any more are not searched
+const MAX_SUB_IFDS: usize = 8;
+
+// the tags that locate data in the file rather than describe the image, and would point nowhere
+// once the fields are written out again
+const LOCATIONS: [exif::Tag; 10] = [
+    SUB_IFDS,
+    exif::Tag::ExifIFDPointer,
+    exif::Tag::GPSInfoIFDPointer,
+    exif::Tag::InteropIFDPointer,
+    exif::Tag::StripOffsets,
+    exif::Tag::StripByteCounts,
+    exif::Tag::TileOffsets,
+    exif::Tag::TileByteCounts,
+    exif::Tag::JPEGInterchangeFormat,
+    exif::Tag::JPEGInterchangeFormatLength,
+];
+
+/// Returns whether `head`, the start of a file, is the start of a TIFF file or one of the raw
+/// formats built on it.
+pub(crate) fn is_tiff(head: &[u8]) -> bool {
+    MAGIC.iter().any(|(magic, _)| head.starts_with(*magic))
+}
+
+/// Reads the Exif attributes of `data`, a whole TIFF or TIFF based raw file.
+pub(crate) fn read_exif(mut data: Vec<u8>) -> Result<exif::Exif, exif::Error> {
+    if let Some((_, standard)) = MAGIC.iter().find(|(magic, _)| data.starts_with(*magic)) {
+        data[..4].copy_from_slice(*standard);
+    }
+    let exif = Reader::new().read_raw(data)?;
+    if exif
+        .get_field(exif::Tag::DateTimeOriginal, In::PRIMARY)
+        .is_some()
+    {
+        return Ok(exif);
+    }
+
+    // read each sub-IFD as if it were IFD0, by pointing the header of one copy of the file at it
+    let mut data = exif.buf().to_vec();
+    for offset in get_sub_ifds(&exif).into_iter().take(MAX_SUB_IFDS) {
+        let offset = if exif.little_endian() {
+            offset.to_le_bytes()
+        } else {
+            offset.to_be_bytes()
+        };
+        data[4..8].copy_from_slice(&offset);
+        if let Ok((sub, _)) = exif::parse_exif(&data) {
+            if sub.iter().any(|field| {
+                field.tag == exif::Tag::DateTimeOriginal && field.ifd_num == In::PRIMARY
+            }) {
+                // the date is what the sub-IFD was read for, so it is kept even if IFD0 is not
+                return merge(&exif, &sub).or_else(|_| Reader::new().read_raw(data));
+            }
+        }
+    }
+    Ok(exif)
+}
+
+// Adds the fields of `sub`, a sub-IFD read as IFD0, that IFD0 of `exif` lacks to those of `exif`,
+// and writes them out as one TIFF structure again
+fn merge(exif: &exif::Exif, sub: &[Field]) -> Result<exif::Exif, exif::Error> {
+    let missing = sub
+        .iter()
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .filter(|field| exif.get_field(field.tag, In::PRIMARY).is_none());
+    let fields: Vec<Field> = exif
+        .fields()
+        .chain(missing)
+        .filter(|field| !LOCATIONS.contains(&field.tag))
+        // the writer cannot write values kamadak-exif could not read
+        .filter(|field| !matches!(field.value, exif::Value::Unknown(..)))
+        .cloned()
+        .collect();
+
+    let mut writer = Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = Cursor::new(Vec::new());
+    writer.write(&mut tiff, exif.little_endian())?;
+    Reader::new().read_raw(tiff.into_inner())
+}
+
+// Returns the offsets of the sub-IFDs of IFD0
+fn get_sub_ifds(exif: &exif::Exif) -> Vec<u32> {
+    let field = match exif.get_field(SUB_IFDS, In::PRIMARY) {
+        Some(field) => field,
+        None => return Vec::new(),
+    };
+    match &field.value {
+        // a single offset of the IFD type is left where it was stored, in the entry itself
+        exif::Value::Unknown(IFD_TYPE, 1, at) => {
+            let at = *at as usize;
+            let bytes = match exif.buf().get(at..at + 4) {
+                Some(bytes) => [bytes[0], bytes[1], bytes[2], bytes[3]],
+                None => return Vec::new(),
+            };
+            if exif.little_endian() {
+                vec![u32::from_le_bytes(bytes)]
+            } else {
+                vec![u32::from_be_bytes(bytes)]
+            }
+        }
+        value => value
+            .iter_uint()
+            .map(|offsets| offsets.collect())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::ascii;
+    use crate::tests::tiff;
+    use crate::DateSource;
+
+    use exif::Tag;
+
+    fn original(date: &str) -> Vec<u8> {
+        tiff(&[ascii(Tag::DateTimeOriginal, In::PRIMARY, date)])
+    }
+
+    // Like `original`, but big endian
+    fn original_be(date: &str) -> Vec<u8> {
+        let field = ascii(Tag::DateTimeOriginal, In::PRIMARY, date);
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn vendor_magic() {
+        for (magic, _) in MAGIC.iter() {
+            let mut file = if magic.starts_with(b"II") {
+                original("2008:05:30 15:56:01")
+            } else {
+                original_be("2008:05:30 15:56:01")
+            };
+            file[..4].copy_from_slice(*magic);
+            assert!(is_tiff(&file), "{:?}", magic);
+
+            let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+            assert_eq!(date.source, DateSource::ExifDateTimeOriginal);
+            assert_eq!(date.timestamp, 1212162961, "{:?}", magic);
+        }
+        assert!(!is_tiff(b"IIXX\0\0\0\0"));
+    }
+
+    // Moves IFD0 of a little endian TIFF to be the only sub-IFD of a new IFD0, which also holds
+    // `extra`, a 12 byte entry
+    fn nest(mut file: Vec<u8>, ifd_type: u16, extra: &[u8]) -> Vec<u8> {
+        let sub_ifd = [file[4], file[5], file[6], file[7]];
+        if file.len() % 2 == 1 {
+            file.push(0);
+        }
+        let ifd0 = file.len() as u32;
+        file[4..8].copy_from_slice(&ifd0.to_le_bytes());
+        file.extend_from_slice(&[2, 0]);
+        file.extend_from_slice(&[0x4a, 0x01]);
+        file.extend_from_slice(&ifd_type.to_le_bytes());
+        file.extend_from_slice(&[1, 0, 0, 0]);
+        file.extend_from_slice(&sub_ifd);
+        file.extend_from_slice(extra);
+        file.extend_from_slice(&[0; 4]);
+        file
+    }
+
+    #[test]
+    fn exif_in_sub_ifd() {
+        // 0x0112 Orientation, a SHORT of 1, to give IFD0 something of its own
+        let orientation = [0x12, 0x01, 3, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+        for ifd_type in [4, IFD_TYPE].iter() {
+            let file = nest(original("2008:05:30 15:56:01"), *ifd_type, &orientation);
+            let exif = read_exif(file.clone()).unwrap();
+            assert!(exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).is_some());
+            // what IFD0 holds is kept too
+            assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_some());
+            assert!(exif.get_field(SUB_IFDS, In::PRIMARY).is_none());
+
+            let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+            assert_eq!(date.timestamp, 1212162961, "type {}", ifd_type);
+        }
+
+        // a sub-IFD with nothing better to offer is ignored
+        let file = nest(
+            tiff(&[ascii(Tag::Make, In::PRIMARY, "NIKON")]),
+            4,
+            &orientation,
+        );
+        let exif = read_exif(file).unwrap();
+        assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_some());
+        assert!(exif.get_field(Tag::Make, In::PRIMARY).is_none());
+
+        // nor is one whose date IFD0 already has
+        let (file, exif_ifd) =
+            with_exif_ifd(original("2001:02:03 04:05:06"), "2008:05:30 15:56:01");
+        let file = nest(file, 4, &exif_ifd);
+        let exif = read_exif(file.clone()).unwrap();
+        let date = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).unwrap();
+        assert_eq!(date.display_value().to_string(), "2008-05-30 15:56:01");
+        let date = crate::try_get_image_date_from_bytes(&file).unwrap();
+        assert_eq!(date.timestamp, 1212162961);
+    }
+
+    // Adds an Exif IFD holding only a DateTimeOriginal of `date` to a little endian TIFF, and
+    // returns it with the 12 byte entry that points to it
+    fn with_exif_ifd(mut file: Vec<u8>, date: &str) -> (Vec<u8>, [u8; 12]) {
+        if file.len() % 2 == 1 {
+            file.push(0);
+        }
+        let ifd = file.len() as u32;
+        file.extend_from_slice(&[1, 0]);
+        file.extend_from_slice(&[0x03, 0x90, 2, 0]);
+        file.extend_from_slice(&(date.len() as u32 + 1).to_le_bytes());
+        file.extend_from_slice(&(ifd + 18).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(date.as_bytes());
+        file.push(0);
+
+        let mut entry = [0x69, 0x87, 4, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+        entry[8..].copy_from_slice(&ifd.to_le_bytes());
+        (file, entry)
+    }
+
+    #[test]
+    fn many_sub_ifds() {
+        // the offsets of as many sub-IFDs as the file can point to, all but one of them to nothing
+        for (at, searched) in [(MAX_SUB_IFDS - 1, true), (MAX_SUB_IFDS, false)].iter() {
+            let mut file = original("2008:05:30 15:56:01");
+            let mut offsets = vec![0xff; 4 * 4096];
+            offsets[4 * at..4 * at + 4].copy_from_slice(&file[4..8]);
+            let start = file.len() as u32;
+            file.extend(offsets);
+            let ifd0 = file.len() as u32;
+            file[4..8].copy_from_slice(&ifd0.to_le_bytes());
+            file.extend_from_slice(&[1, 0, 0x4a, 0x01, 4, 0, 0, 0x10, 0, 0]);
+            file.extend_from_slice(&start.to_le_bytes());
+            file.extend_from_slice(&[0; 4]);
+
+            let exif = read_exif(file).unwrap();
+            let date = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY);
+            assert_eq!(date.is_some(), *searched, "sub-IFD {}", at);
+        }
+    }
+}
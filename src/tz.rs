@@ -0,0 +1,112 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Resolves the offset of an Exif time that carries none from the time zone at the image's GPS
+// coordinates, looked up in the embedded time zone boundaries. A local time the clocks skipped
+// over takes the offset in effect a day earlier, and one they repeated the earliest of its two.
+
+use std::sync::OnceLock;
+
+use exif::In;
+use exif::Tag;
+
+use chrono::Duration;
+use chrono::FixedOffset;
+use chrono::LocalResult;
+use chrono::NaiveDateTime;
+use chrono::Offset;
+use chrono::TimeZone;
+use chrono_tz::Tz;
+
+use tzf_rs::EmbeddedFinder;
+
+/// Returns the offset in effect, at the local time `local`, where the image's GPS coordinates say
+/// it was taken.
+pub(crate) fn get_exif_location_offset(
+    exif: &exif::Exif,
+    local: NaiveDateTime,
+) -> Option<FixedOffset> {
+    let latitude = get_exif_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let longitude = get_exif_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+    get_location_offset(latitude, longitude, local)
+}
+
+/// Converts a GPS degrees, minutes and seconds triple into signed decimal degrees, negative when
+/// the reference is `negative` (south or west).
+fn get_exif_coordinate(exif: &exif::Exif, tag: Tag, reference: Tag, negative: u8) -> Option<f64> {
+    let degrees = match &exif.get_field(tag, In::PRIMARY)?.value {
+        exif::Value::Rational(dms) if dms.len() == 3 => {
+            dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0
+        }
+        _ => return None,
+    };
+    if !degrees.is_finite() {
+        return None; // a zero denominator
+    }
+
+    match &exif.get_field(reference, In::PRIMARY)?.value {
+        exif::Value::Ascii(reference) => match reference.first()?.first()? {
+            r if *r == negative => Some(-degrees),
+            _ => Some(degrees),
+        },
+        _ => None,
+    }
+}
+
+fn get_location_offset(latitude: f64, longitude: f64, local: NaiveDateTime) -> Option<FixedOffset> {
+    // the finder reads the embedded dataset in place, but indexing its zone names is still worth
+    // doing only once
+    static FINDER: OnceLock<EmbeddedFinder> = OnceLock::new();
+    let name = FINDER
+        .get_or_init(EmbeddedFinder::new)
+        .get_tz_name(longitude, latitude);
+    let zone: Tz = name.parse().ok()?;
+
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(date_time) => Some(date_time.offset().fix()),
+        // during the hour the clocks go back we cannot tell, so assume the earlier of the two
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.offset().fix()),
+        // the hour the clocks go forward does not exist, but a camera that was not adjusted yet
+        // will still record it, in the offset from before the change
+        LocalResult::None => zone
+            .from_local_datetime(&(local - Duration::days(1)))
+            .earliest()
+            .map(|date_time| date_time.offset().fix()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(date_time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date_time, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn location_offset() {
+        // Tokyo has no daylight saving
+        let offset = get_location_offset(35.6812, 139.7671, local("2008-05-30 15:56:01"));
+        assert_eq!(offset, FixedOffset::east_opt(9 * 3600));
+
+        // New York does, so the offset depends on the date
+        let summer = get_location_offset(40.7128, -74.0060, local("2020-07-01 12:00:00"));
+        assert_eq!(summer, FixedOffset::west_opt(4 * 3600));
+        let winter = get_location_offset(40.7128, -74.0060, local("2020-01-01 12:00:00"));
+        assert_eq!(winter, FixedOffset::west_opt(5 * 3600));
+
+        // 2:30 on the day the clocks went forward never happened
+        let skipped = get_location_offset(40.7128, -74.0060, local("2020-03-08 02:30:00"));
+        assert_eq!(skipped, FixedOffset::west_opt(5 * 3600));
+    }
+}
@@ -0,0 +1,25 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Helpers shared by the readers of the container formats.
+
+use std::io;
+
+/// The largest metadata chunk read. Metadata chunks are small, so anything larger is corrupt, or
+/// image data we are not after.
+pub(crate) const MAX_CHUNK_LEN: u32 = 16 * 1024 * 1024;
+
+/// Returns the error for input that is not laid out as its format says.
+pub(crate) fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,128 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// WebP is a RIFF file, whose chunks have a little endian size and are padded to an even length.
+// The extended format keeps metadata in `EXIF` and `XMP ` chunks.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::invalid_data;
+use crate::util::MAX_CHUNK_LEN;
+
+/// Returns whether `head`, the start of a file, is the start of a WebP file.
+pub(crate) fn is_webp(head: &[u8]) -> bool {
+    head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP"
+}
+
+/// Reads the data of the first chunk of type `kind` in the WebP file `reader` is at the start of.
+pub(crate) fn read_chunk<R: Read + Seek>(
+    reader: &mut R,
+    kind: &[u8; 4],
+) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if !is_webp(&header) {
+        return Err(invalid_data("not a WebP file"));
+    }
+
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if header[..4] == kind[..] {
+            if len > MAX_CHUNK_LEN {
+                return Err(invalid_data("chunk is too large"));
+            }
+            let mut data = Vec::new();
+            reader.take(u64::from(len)).read_to_end(&mut data)?;
+            return Ok(Some(data));
+        }
+        reader.seek(SeekFrom::Current(i64::from(len) + i64::from(len % 2)))?;
+    }
+}
+
+/// Reads the Exif attributes of the WebP file `reader` is at the start of, from its `EXIF` chunk.
+pub(crate) fn get_exif_attr<R: Read + Seek>(reader: &mut R) -> Result<Vec<u8>, exif::Error> {
+    let data = read_chunk(reader, b"EXIF")?.ok_or(exif::Error::NotFound("WebP"))?;
+    // the chunk should hold a bare TIFF structure, but some writers copy the JPEG APP1 prefix too
+    match data.strip_prefix(b"Exif\0\0") {
+        Some(tiff) => Ok(tiff.to_vec()),
+        None => Ok(data),
+    }
+}
+
+/// Builds WebP files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    pub(crate) fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    // An extended WebP file with the given chunks after its header and image
+    pub(crate) fn webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut body = b"WEBP".to_vec();
+        body.extend(chunk(b"VP8X", &[0; 10]));
+        body.extend(chunk(b"VP8 ", &[0; 15]));
+        for data in chunks {
+            body.extend_from_slice(data);
+        }
+        let mut file = b"RIFF".to_vec();
+        file.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        file.extend(body);
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn chunks() {
+        let file = webp(&[chunk(b"EXIF", b"MM\0\x2a"), chunk(b"XMP ", b"<x:xmpmeta/>")]);
+        assert!(is_webp(&file));
+        let xmp = read_chunk(&mut Cursor::new(&file), b"XMP ").unwrap();
+        assert_eq!(xmp.as_deref(), Some(&b"<x:xmpmeta/>"[..]));
+        let iccp = read_chunk(&mut Cursor::new(&file), b"ICCP").unwrap();
+        assert_eq!(iccp, None);
+        assert!(!is_webp(b"RIFF\0\0\0\0WAVE"));
+    }
+
+    #[test]
+    fn exif_chunk() {
+        let file = webp(&[chunk(b"EXIF", b"Exif\0\0MM\0\x2a")]);
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), b"MM\0\x2a");
+        let file = webp(&[chunk(b"EXIF", b"II\x2a\0")]);
+        assert_eq!(get_exif_attr(&mut Cursor::new(&file)).unwrap(), b"II\x2a\0");
+        match get_exif_attr(&mut Cursor::new(&webp(&[]))) {
+            Err(exif::Error::NotFound(_)) => (),
+            other => panic!("Expected no Exif, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Sigma's X3F ends with a directory of the sections in the file. Among them, the property list
+// (PROP) holds name and value pairs of UTF-16 text, including TIME, when the image was captured.
+// Everything is little endian.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+use crate::util::invalid_data;
+
+const MAGIC: &[u8; 4] = b"FOVb";
+
+// a list of short text properties, so anything larger is corrupt
+const MAX_SECTION_LEN: u32 = 16 * 1024 * 1024;
+
+/// Returns whether `head`, the start of a file, is the start of an X3F file.
+pub(crate) fn is_x3f(head: &[u8]) -> bool {
+    head.starts_with(MAGIC)
+}
+
+/// Returns the value of the property `name` of the X3F file `reader` is at the start of.
+pub(crate) fn get_property<R: Read + Seek>(
+    reader: &mut R,
+    name: &str,
+) -> io::Result<Option<String>> {
+    let start = reader.stream_position()?;
+
+    // the last four bytes point to the directory
+    reader.seek(SeekFrom::End(-4))?;
+    let directory = read_u32(reader)?;
+    reader.seek(SeekFrom::Start(start + u64::from(directory)))?;
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != b"SECd" {
+        return Err(invalid_data("missing X3F directory"));
+    }
+    let count = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+    let mut section = None;
+    for _ in 0..count {
+        let offset = read_u32(reader)?;
+        let len = read_u32(reader)?;
+        let mut kind = [0; 4];
+        reader.read_exact(&mut kind)?;
+        if kind == *b"PROP" {
+            section = Some((offset, len));
+            break;
+        }
+    }
+    let (offset, len) = match section {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    if len > MAX_SECTION_LEN {
+        return Err(invalid_data("X3F property list is too large"));
+    }
+
+    reader.seek(SeekFrom::Start(start + u64::from(offset)))?;
+    let mut data = Vec::new();
+    reader.take(u64::from(len)).read_to_end(&mut data)?;
+    Ok(find_property(&data, name))
+}
+
+// Finds a property in a PROP section: a header, then the offsets of each name and value, in
+// characters from the start of the text after them
+fn find_property(section: &[u8], name: &str) -> Option<String> {
+    let u32_at = |at: usize| {
+        let bytes = section.get(at..at + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+    if section.get(..4)? != b"SECp" {
+        return None;
+    }
+    let count = u32_at(8)?;
+    // 0 is the only format, UTF-16
+    if u32_at(12)? != 0 {
+        return None;
+    }
+    let text_start = 24usize.checked_add(count.checked_mul(8)?)?;
+    let text: Vec<u16> = section
+        .get(text_start..)?
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    // each string runs to a NUL
+    let string_at = |at: usize| {
+        let units = text.get(at..)?;
+        let end = units.iter().position(|&unit| unit == 0)?;
+        String::from_utf16(&units[..end]).ok()
+    };
+
+    (0..count).find_map(|i| {
+        let entry = 24 + i * 8;
+        if string_at(u32_at(entry)?)? == name {
+            string_at(u32_at(entry + 4)?)
+        } else {
+            None
+        }
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Builds X3F files for the tests.
+#[cfg(test)]
+pub(crate) mod build {
+    // An X3F file whose only section is a property list with the given properties
+    pub(crate) fn x3f(properties: &[(&str, &str)]) -> Vec<u8> {
+        let mut text: Vec<u16> = Vec::new();
+        let mut entries = Vec::new();
+        for (name, value) in properties {
+            for string in &[name, value] {
+                entries.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                text.extend(string.encode_utf16());
+                text.push(0);
+            }
+        }
+        let mut section = b"SECp".to_vec();
+        section.extend_from_slice(&[0, 0, 2, 0]); // version 2.0
+        section.extend_from_slice(&(properties.len() as u32).to_le_bytes());
+        section.extend_from_slice(&[0; 8]); // format and reserved
+        section.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        section.extend(entries);
+        section.extend(text.iter().flat_map(|unit| unit.to_le_bytes().to_vec()));
+
+        let mut file = super::MAGIC.to_vec();
+        file.resize(256, 0); // the rest of the header
+        let offset = file.len() as u32;
+        file.extend_from_slice(&section);
+        let directory = file.len() as u32;
+        file.extend_from_slice(b"SECd");
+        file.extend_from_slice(&[0, 0, 2, 0]);
+        file.extend_from_slice(&1u32.to_le_bytes());
+        file.extend_from_slice(&offset.to_le_bytes());
+        file.extend_from_slice(&(section.len() as u32).to_le_bytes());
+        file.extend_from_slice(b"PROP");
+        file.extend_from_slice(&directory.to_le_bytes());
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build::*;
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn properties() {
+        let file = x3f(&[("CAMMANUF", "SIGMA"), ("TIME", "1212162961")]);
+        assert!(is_x3f(&file));
+        let time = get_property(&mut Cursor::new(&file), "TIME").unwrap();
+        assert_eq!(time.as_deref(), Some("1212162961"));
+        let model = get_property(&mut Cursor::new(&file), "CAMMODEL").unwrap();
+        assert_eq!(model, None);
+
+        let mut file = x3f(&[]);
+        let len = file.len();
+        file[len - 4..].copy_from_slice(&[0; 4]);
+        assert!(get_property(&mut Cursor::new(&file), "TIME").is_err());
+    }
+}
@@ -0,0 +1,190 @@
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// XMP packets are RDF/XML. Rather than parse the XML, the few simple properties we need are picked
+// out by their qualified names, which relies on the namespace prefixes being the conventional ones.
+// Every writer we know of uses them.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+// sidecars hold metadata only, so anything larger is not one
+const MAX_SIDECAR_LEN: u64 = 16 * 1024 * 1024;
+
+/// The properties dates are read from, highest priority first.
+pub(crate) const DATE_PROPERTIES: [&str; 3] = [
+    "exif:DateTimeOriginal",
+    "xmp:CreateDate",
+    "photoshop:DateCreated",
+];
+
+/// Returns the paths a sidecar of the image at `path` may have, in the order they are tried: the
+/// image's whole name with ".xmp" appended, as darktable and digiKam write them, then its name
+/// with the extension replaced, as Lightroom does.
+pub(crate) fn sidecar_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for extension in &["xmp", "XMP"] {
+        let mut appended = OsString::from(path.as_os_str());
+        appended.push(".");
+        appended.push(extension);
+        paths.push(PathBuf::from(appended));
+    }
+    if path.extension().is_some() {
+        for extension in &["xmp", "XMP"] {
+            paths.push(path.with_extension(extension));
+        }
+    }
+    paths
+}
+
+/// Reads the first sidecar of the image at `path` that exists, returning its path and contents.
+pub(crate) fn read_sidecar(path: &Path) -> Option<(PathBuf, String)> {
+    sidecar_paths(path).into_iter().find_map(|sidecar| {
+        let mut contents = Vec::new();
+        File::open(&sidecar)
+            .ok()?
+            .take(MAX_SIDECAR_LEN)
+            .read_to_end(&mut contents)
+            .ok()?;
+        Some((sidecar, String::from_utf8_lossy(&contents).into_owned()))
+    })
+}
+
+/// Returns the value of the simple property `name`, e.g. "xmp:CreateDate", whether it is written
+/// as an attribute of an `rdf:Description` or as an element of its own.
+pub(crate) fn get_property(xmp: &str, name: &str) -> Option<String> {
+    let mut from = 0;
+    while let Some(found) = xmp[from..].find(name) {
+        let start = from + found;
+        let end = start + name.len();
+        from = end;
+
+        let before = xmp[..start].chars().next_back();
+        let rest = &xmp[end..];
+        match before {
+            // <exif:DateTimeOriginal>2008-05-30T15:56:01</exif:DateTimeOriginal>
+            Some('<') => {
+                if !rest.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+                    continue;
+                }
+                let open_end = rest.find('>')?;
+                if rest[..open_end].ends_with('/') {
+                    continue; // an empty element
+                }
+                let content = &rest[open_end + 1..];
+                let close = content.find(&format!("</{}", name))?;
+                return Some(unescape(strip_tags(&content[..close]).trim()));
+            }
+            // exif:DateTimeOriginal="2008-05-30T15:56:01"
+            Some(c) if c.is_whitespace() => {
+                let value = match rest.trim_start().strip_prefix('=') {
+                    Some(value) => value.trim_start(),
+                    None => continue,
+                };
+                let quote = match value.chars().next() {
+                    Some(quote) if quote == '"' || quote == '\'' => quote,
+                    _ => continue,
+                };
+                let value = &value[1..];
+                let close = value.find(quote)?;
+                return Some(unescape(value[..close].trim()));
+            }
+            _ => continue,
+        }
+    }
+    None
+}
+
+// Drops any markup inside a property, such as the rdf:Alt some writers wrap values in, keeping the
+// first piece of text
+fn strip_tags(content: &str) -> &str {
+    content
+        .split(['<', '>'])
+        .enumerate()
+        // the pieces alternate between text and markup
+        .filter(|(i, _)| i % 2 == 0)
+        .map(|(_, text)| text.trim())
+        .find(|text| !text.is_empty())
+        .unwrap_or("")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XMP: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:exif="http://ns.adobe.com/exif/1.0/"
+    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+    xmp:CreateDate = '2008-05-30T15:56:01+09:00'
+    xmp:ModifyDate="2010-01-01T00:00:00"
+    xmp:Label="Tom &amp; Jerry">
+   <exif:DateTimeOriginal>2008-05-30T15:56:01.50+09:00</exif:DateTimeOriginal>
+   <photoshop:DateCreated>
+    <rdf:Alt><rdf:li xml:lang="x-default">2008-05-30</rdf:li></rdf:Alt>
+   </photoshop:DateCreated>
+   <xmp:MetadataDate/>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    #[test]
+    fn properties() {
+        let cases = [
+            ("xmp:CreateDate", Some("2008-05-30T15:56:01+09:00")),
+            ("xmp:ModifyDate", Some("2010-01-01T00:00:00")),
+            ("xmp:Label", Some("Tom & Jerry")),
+            (
+                "exif:DateTimeOriginal",
+                Some("2008-05-30T15:56:01.50+09:00"),
+            ),
+            ("photoshop:DateCreated", Some("2008-05-30")),
+            ("xmp:MetadataDate", None),
+            ("exif:DateTime", None),
+        ];
+        for (name, expected) in cases.iter() {
+            assert_eq!(get_property(XMP, name).as_deref(), *expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn sidecars() {
+        let paths = sidecar_paths(Path::new("photos/IMG_1234.CR2"));
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("photos/IMG_1234.CR2.xmp"),
+                PathBuf::from("photos/IMG_1234.CR2.XMP"),
+                PathBuf::from("photos/IMG_1234.xmp"),
+                PathBuf::from("photos/IMG_1234.XMP"),
+            ]
+        );
+        assert_eq!(sidecar_paths(Path::new("IMG_1234")).len(), 2);
+    }
+}